@@ -0,0 +1,201 @@
+use crate::constants::*;
+use crate::eval::*;
+use crate::rng::*;
+use crate::tetrus::*;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "medium" => Some(Difficulty::Medium),
+            "hard" => Some(Difficulty::Hard),
+            "expert" => Some(Difficulty::Expert),
+            _ => None,
+        }
+    }
+
+    pub fn next(&self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Medium,
+            Difficulty::Medium => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Expert,
+            Difficulty::Expert => Difficulty::Easy,
+        }
+    }
+}
+
+struct Search {
+    candidates: Vec<Placement>,
+    scores: Vec<f64>,
+    inactive: Vec<Block>,
+    next: Option<BlockType>,
+    weights: Weights,
+}
+
+impl Search {
+    fn new(tetrus: &Tetrus, depth: usize, weights: Weights) -> Self {
+        Search {
+            candidates: placements(
+                &tetrus.active,
+                tetrus.get_origin(),
+                tetrus.get_block_id(),
+                &tetrus.inactive,
+            ),
+            scores: Vec::new(),
+            inactive: tetrus.inactive.clone(),
+            next: match depth {
+                1 => None,
+                _ => tetrus.get_queue().front().cloned(),
+            },
+            weights,
+        }
+    }
+
+    fn step(&mut self, budget: usize) -> bool {
+        for _ in 0..budget {
+            match self.candidates.get(self.scores.len()) {
+                Some(placement) => {
+                    let score = self.score(placement);
+                    self.scores.push(score);
+                }
+                None => return true,
+            }
+        }
+        self.scores.len() == self.candidates.len()
+    }
+
+    fn score(&self, placement: &Placement) -> f64 {
+        let (board, lines, eroded) = place(&self.inactive, &placement.blocks);
+        let score = evaluate(&board, &placement.blocks, lines, eroded, &self.weights);
+        let id = match &self.next {
+            Some(id) if score > TOP_OUT_SCORE => id,
+            _ => return score,
+        };
+        let (active, origin) = spawn(id);
+        let next = placements(&active, &origin, id, &board)
+            .iter()
+            .map(|next| {
+                let (next_board, next_lines, next_eroded) = place(&board, &next.blocks);
+                evaluate(
+                    &next_board,
+                    &next.blocks,
+                    next_lines,
+                    next_eroded,
+                    &self.weights,
+                )
+            })
+            .fold(TOP_OUT_SCORE, f64::max);
+        score + next
+    }
+}
+
+pub trait Controller {
+    fn update(&mut self, tetrus: &mut Tetrus);
+
+    fn is_waiting(&self) -> bool {
+        false
+    }
+}
+
+pub struct Bot {
+    difficulty: Difficulty,
+    weights: Weights,
+    piece: u32,
+    search: Option<Search>,
+    plan: VecDeque<Movement>,
+    interval: u32,
+    delay: u32,
+    rng: Rng,
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        Bot {
+            difficulty,
+            weights: Weights::default(),
+            piece: 0,
+            search: None,
+            plan: VecDeque::new(),
+            interval: 0,
+            delay: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = weights;
+    }
+
+    fn choose(&mut self, search: Search) -> Vec<Movement> {
+        let mut ranked: Vec<(f64, Vec<Movement>)> = search
+            .scores
+            .into_iter()
+            .zip(search.candidates.into_iter().map(|p| p.moves))
+            .collect();
+        if ranked.is_empty() {
+            return Vec::new();
+        }
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let index = if self.rng.gen_range(0, 100) < BOT_MISTAKES[self.difficulty as usize] {
+            self.rng.gen_range(0, ranked.len().div_ceil(2))
+        } else {
+            0
+        };
+        ranked.swap_remove(index).1
+    }
+}
+
+impl Controller for Bot {
+    fn update(&mut self, tetrus: &mut Tetrus) {
+        if !tetrus.is_active() {
+            return;
+        }
+        let tier = self.difficulty as usize;
+        if tetrus.get_pieces() != self.piece {
+            self.piece = tetrus.get_pieces();
+            self.plan.clear();
+            self.search = Some(Search::new(tetrus, BOT_DEPTH[tier], self.weights));
+        }
+        if let Some(mut search) = self.search.take() {
+            if !search.step(BOT_BUDGET) {
+                self.search = Some(search);
+                return;
+            }
+            self.plan = self.choose(search).into();
+            let frames = (FRAME_RATE / BOT_SPEED[tier]) as u32;
+            self.interval = (frames / self.plan.len().max(1) as u32).max(1);
+            self.delay = self.interval;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+        if let Some(movement) = self.plan.pop_front() {
+            tetrus.player_move(movement);
+            self.delay = self.interval;
+            if movement == Movement::Drop {
+                while let Some(movement) = self.plan.pop_front() {
+                    tetrus.player_move(movement);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use tetrus::constants::*;
+use tetrus::editor::*;
+use tetrus::fumen::*;
+use tetrus::sim::*;
+use tetrus::tetrus::*;
+
+const USAGE: &str = "usage: tetrus-fumen decode FUMEN [PAGE] | show FUMEN [PAGE] | encode FILE | game [--seed N] [--pieces N] [--player easy|medium|hard|expert|tbp:COMMAND|script:FILE]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("decode") => println!("{}", Editor::from_page(&get_page(&args[1..])).to_text()),
+        Some("show") => show_page(&args[1..]),
+        Some("encode") => encode_file(&args[1..]),
+        Some("game") => encode_game(&args[1..]),
+        _ => exit(USAGE),
+    }
+}
+
+fn get_page(args: &[String]) -> Page {
+    let (text, page) = match args {
+        [text] => (text, 1),
+        [text, page] => (text, parse(page)),
+        _ => exit(USAGE),
+    };
+    let pages = decode(text).unwrap_or_else(|e| exit(&e));
+    match pages.get(page.max(1) - 1) {
+        Some(page) => page.clone(),
+        None => exit(&format!("fumen has {} pages", pages.len())),
+    }
+}
+
+fn show_page(args: &[String]) {
+    let page = get_page(args);
+    let mut tetrus = Tetrus::new();
+    tetrus.set_board(page.field);
+    if !page.piece.is_empty() {
+        tetrus.set_active(page.piece).unwrap_or_else(|e| exit(&e));
+    }
+    if !page.comment.is_empty() {
+        println!("{}", page.comment);
+    }
+    println!("{}", tetrus.to_text());
+}
+
+fn encode_file(args: &[String]) {
+    let file = match args {
+        [file] => file,
+        _ => exit(USAGE),
+    };
+    let text = std::fs::read_to_string(file).unwrap_or_else(|e| exit(&format!("{}: {}", file, e)));
+    let editor = Editor::parse(&text).unwrap_or_else(|e| exit(&e));
+    println!("{}", encode(&[editor.to_page()]));
+}
+
+fn encode_game(args: &[String]) {
+    let mut seed = 0;
+    let mut pieces = SIM_MAX_PIECES;
+    let mut player = String::from("hard");
+    for pair in args.chunks(2) {
+        let value = match pair {
+            [_, value] => value,
+            _ => exit(USAGE),
+        };
+        match pair[0].as_str() {
+            "--seed" => seed = parse(value),
+            "--pieces" => pieces = parse(value),
+            "--player" => player = value.clone(),
+            _ => exit(USAGE),
+        }
+    }
+    let player = Player::parse(&player).unwrap_or_else(|e| exit(&e));
+    let mut pages = Vec::new();
+    play(&player, seed, pieces, |tetrus, event| {
+        if *event == Event::Lock {
+            pages.push(Page::from_lock(tetrus));
+        }
+    })
+    .unwrap_or_else(|e| exit(&e));
+    println!("{}", encode(&pages));
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit(USAGE))
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,71 @@
+use tetrus::constants::*;
+use tetrus::eval::*;
+use tetrus::json::*;
+use tetrus::sim::*;
+
+const USAGE: &str = "usage: tetrus-sim [--games N] [--seed N] [--pieces N] [--player easy|medium|hard|expert|tbp:COMMAND|script:FILE] [--weights FILE] [--format csv|json]";
+
+fn main() {
+    let mut games = SIM_GAMES;
+    let mut seed = 0;
+    let mut pieces = SIM_MAX_PIECES;
+    let mut player = String::from("hard");
+    let mut weights = None;
+    let mut json = false;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = match pair {
+            [_, value] => value,
+            _ => exit(USAGE),
+        };
+        match pair[0].as_str() {
+            "--games" => games = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--seed" => seed = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--pieces" => pieces = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--player" => player = value.clone(),
+            "--weights" => weights = Some(value.clone()),
+            "--format" if value == "csv" => json = false,
+            "--format" if value == "json" => json = true,
+            _ => exit(USAGE),
+        }
+    }
+    let mut player = Player::parse(&player).unwrap_or_else(|e| exit(&e));
+    if let (Some(file), Player::Bot(_, weights)) = (weights, &mut player) {
+        let text =
+            std::fs::read_to_string(&file).unwrap_or_else(|e| exit(&format!("{}: {}", file, e)));
+        *weights = Weights::parse(&text).unwrap_or_else(|e| exit(&e));
+    }
+
+    let mut results = Vec::new();
+    if !json {
+        println!("{}", GameStats::csv_header());
+    }
+    for i in 0..games {
+        let stats = simulate(&player, seed + i, pieces).unwrap_or_else(|e| exit(&e));
+        if !json {
+            println!("{}", stats.to_csv());
+        }
+        results.push(stats);
+    }
+    if json {
+        println!(
+            "{}",
+            Json::Array(results.iter().map(|stats| stats.to_json()).collect())
+        );
+    }
+
+    let count = results.len().max(1) as f64;
+    eprintln!(
+        "{} games: mean score {:.1}, mean lines {:.1}, mean pieces {:.1}",
+        results.len(),
+        results.iter().map(|s| s.score as f64).sum::<f64>() / count,
+        results.iter().map(|s| s.lines as f64).sum::<f64>() / count,
+        results.iter().map(|s| s.pieces as f64).sum::<f64>() / count,
+    );
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,76 @@
+use tetrus::ai::*;
+use tetrus::constants::*;
+use tetrus::eval::*;
+use tetrus::train::*;
+
+const USAGE: &str = "usage: tetrus-train [--population N] [--generations N] [--games N] [--pieces N] [--fitness lines|score] [--player easy|medium|hard|expert] [--threads N] [--seed N] [--checkpoint FILE] [--output FILE]";
+
+fn main() {
+    let mut settings = TrainSettings::default();
+    let mut output = String::from(TRAIN_OUTPUT);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = match pair {
+            [_, value] => value,
+            _ => exit(USAGE),
+        };
+        match pair[0].as_str() {
+            "--population" => settings.population = parse(value),
+            "--generations" => settings.generations = parse(value),
+            "--games" => settings.games = parse(value),
+            "--pieces" => settings.pieces = parse(value),
+            "--fitness" if value == "lines" => settings.fitness = Fitness::Lines,
+            "--fitness" if value == "score" => settings.fitness = Fitness::Score,
+            "--player" => {
+                settings.difficulty = Difficulty::parse(value).unwrap_or_else(|| exit(USAGE))
+            }
+            "--threads" => settings.threads = parse(value),
+            "--seed" => settings.seed = parse(value),
+            "--checkpoint" => settings.checkpoint = value.clone(),
+            "--output" => output = value.clone(),
+            _ => exit(USAGE),
+        }
+    }
+    if settings.population < TRAIN_ELITE.max(2) {
+        exit("population is too small");
+    }
+
+    let mut trainer = match std::path::Path::new(&settings.checkpoint).exists() {
+        true => {
+            let trainer = Trainer::resume(settings).unwrap_or_else(|e| exit(&e));
+            eprintln!("resuming at generation {}", trainer.get_generation());
+            trainer
+        }
+        false => Trainer::new(settings),
+    };
+
+    while !trainer.is_finished() {
+        trainer.evaluate_all();
+        let best = trainer.get_best().clone();
+        eprintln!(
+            "generation {}: best {:.1}, mean {:.1}",
+            trainer.get_generation(),
+            best.fitness,
+            trainer.get_mean()
+        );
+        let weights = Weights::from_array(best.weights).to_text();
+        if let Err(e) = std::fs::write(&output, &weights) {
+            exit(&format!("{}: {}", output, e));
+        }
+        trainer.breed();
+        trainer.save();
+    }
+    if let Ok(weights) = std::fs::read_to_string(&output) {
+        print!("{}", weights);
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit(USAGE))
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,22 @@
+use macroquad::rand::srand;
+use tetrus::tui::*;
+
+const USAGE: &str = "usage: tetrus-tui [--bot COMMAND]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bot_command = match args.as_slice() {
+        [] => None,
+        [flag, command] if flag == "--bot" => Some(command.clone()),
+        _ => exit(USAGE),
+    };
+    srand(macroquad::miniquad::date::now() as u64);
+    if let Err(e) = Tui::new(bot_command).run() {
+        exit(&e);
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
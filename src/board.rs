@@ -0,0 +1,94 @@
+use crate::constants::*;
+use crate::tetrus::*;
+
+// Rows are aligned to the bottom of the well. Returns the locked cells and the active cells.
+pub fn parse_rows(rows: &[&str]) -> Result<(Vec<Block>, Vec<Block>), String> {
+    if rows.len() > GRID_HEIGHT {
+        return Err(String::from("board is taller than the grid"));
+    }
+    let mut board = Vec::new();
+    let mut active = Vec::new();
+    let top = GRID_HEIGHT - rows.len();
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != GRID_WIDTH {
+            return Err(format!("board row must be {} cells: {}", GRID_WIDTH, row));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let block = Block {
+                position: Position::new((x, top + y)),
+                color: match c {
+                    EMPTY_CELL => continue,
+                    GARBAGE_CELL | ACTIVE_CELL => GREY,
+                    c => match BlockType::from_char(c) {
+                        Some(id) => id.get_color(),
+                        None => return Err(format!("invalid cell: {}", c)),
+                    },
+                },
+                locked_at: 0,
+            };
+            match c {
+                ACTIVE_CELL => active.push(block),
+                _ => board.push(block),
+            }
+        }
+    }
+    Ok((board, active))
+}
+
+pub fn parse_board(rows: &[&str]) -> Result<Vec<Block>, String> {
+    if rows.len() > GRID_HEIGHT - 4 {
+        return Err(String::from("board is taller than the well"));
+    }
+    match parse_rows(rows)? {
+        (board, active) if active.is_empty() => Ok(board),
+        _ => Err(format!("invalid cell: {}", ACTIVE_CELL)),
+    }
+}
+
+pub fn format_board(board: &[Block], active: &[Block]) -> Vec<String> {
+    let top = board
+        .iter()
+        .chain(active)
+        .map(|n| n.position.y)
+        .min()
+        .unwrap_or(GRID_HEIGHT);
+    let mut rows = vec![vec![EMPTY_CELL; GRID_WIDTH]; GRID_HEIGHT - top];
+    for block in board {
+        rows[block.position.y - top][block.position.x] =
+            BlockType::from_color(block.color).map_or(GARBAGE_CELL, |id| id.to_char());
+    }
+    for block in active {
+        rows[block.position.y - top][block.position.x] = ACTIVE_CELL;
+    }
+    rows.into_iter()
+        .map(|row| row.into_iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_board_rejects_active_cells() {
+        assert!(parse_board(&["IIII..ZZ..", "GGGGG.GGGG"]).is_ok());
+        assert_eq!(
+            parse_board(&["....@.....", "GGGGG.GGGG"]).err(),
+            Some(format!("invalid cell: {}", ACTIVE_CELL))
+        );
+    }
+
+    #[test]
+    fn parse_board_checks_row_width() {
+        assert_eq!(
+            parse_board(&["GGGGG.GGG"]).err(),
+            Some(String::from("board row must be 10 cells: GGGGG.GGG"))
+        );
+        assert!(parse_board(&["GGGGG.GGGGG"]).is_err());
+        assert!(parse_board(&[""]).is_err());
+        assert_eq!(
+            parse_board(&["GGGGG.GGGX"]).err(),
+            Some(String::from("invalid cell: X"))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::miniquad::conf::Icon;
+use macroquad::miniquad::conf::Icon;
 use macroquad::{prelude::*, audio::PlaySoundParams};
 
 use crate::icons::{ICON_SMALL, ICON_MEDIUM, ICON_BIG};
@@ -11,10 +11,32 @@ pub const ICON: Icon = Icon {
 
 pub const GRID_WIDTH: usize = 10;
 pub const GRID_HEIGHT: usize = 24;
+pub const HIDDEN_ROWS: usize = 4;
+pub const VISIBLE_ROWS: usize = GRID_HEIGHT - HIDDEN_ROWS;
 pub const DISPLAY_PADDING: f32 = 100.0;
 pub const DISPLAY_WIDTH: f32 = 600.0;
 pub const DISPLAY_HEIGHT: f32 = 1000.0;
-pub const BLOCK_SIZE: f32 = 40.0;
+// Side panels, top and bottom strips and the gap between versus wells, in cells
+pub const LAYOUT_PANEL: f32 = 4.0;
+pub const LAYOUT_MARGIN: f32 = 2.0;
+pub const LAYOUT_GAP: f32 = 2.0;
+
+// Effect lengths in frames
+pub const EFFECT_FLASH_FRAMES: u64 = 8;
+pub const EFFECT_COLLAPSE_FRAMES: u64 = 8;
+pub const EFFECT_LOCK_FRAMES: u64 = 10;
+pub const EFFECT_TRAIL_FRAMES: u64 = 12;
+pub const EFFECT_SHAKE_FRAMES: u64 = 18;
+pub const EFFECT_PARTICLE_FRAMES: u32 = 40;
+pub const EFFECT_PARTICLES_PER_CELL: usize = 3;
+// In cells
+pub const EFFECT_SHAKE_AMPLITUDE: f32 = 0.3;
+pub const EFFECT_PARTICLE_GRAVITY: f32 = 0.012;
+
+pub const FRAME_RATE: f64 = 60.0;
+pub const GRAVITY_UNIT: u32 = 256;
+pub const SOFT_DROP_TICK: f64 = 0.1;
+pub const PREVIEW_COUNT: usize = 5;
 
 pub const YELLOW: Color = color_u8!(0xfa, 0xff, 0x00, 0xff); //faff00
 pub const CYAN: Color = color_u8!(0x00, 0xe4, 0xff, 0xff); //00e4ff
@@ -24,6 +46,7 @@ pub const ORANGE: Color = color_u8!(0xff, 0x8d, 0x00, 0xff); //ff8d00
 pub const PINK: Color = color_u8!(0xff, 0x51, 0xbc, 0xff); //ff51bc
 pub const PURPLE: Color = color_u8!(0x9f, 0x00, 0x96, 0xff); //9f0096
 pub const _WHITE: Color = color_u8!(0xff, 0xff, 0xff, 0xff); //ffffff
+pub const GREY: Color = color_u8!(0x80, 0x80, 0x80, 0xff); //808080
 pub const BLACK: Color = color_u8!(0x00, 0x00, 0x00, 0xff); //000000
 
 pub const IBLOCK: [(usize, usize); 4] = [(5, 0), (5, 1), (5, 2), (5, 3)];
@@ -46,6 +69,211 @@ pub const TETRUS_TEXT: &str = "Tetrus";
 pub const SPACE_TEXT: &str = "Press Space";
 pub const GAME_OVER_TEXT: &str = "Game Over";
 pub const SCORE_TEXT_PLACEHOLDER: &str = "Score: 00000";
+pub const MODE_TEXT_PLACEHOLDER: &str = "< Survival >";
+pub const PUZZLES_TEXT: &str = "Puzzles";
+pub const NO_PUZZLES_TEXT: &str = "No puzzles found";
+pub const OPENERS_TEXT: &str = "Openers";
+pub const NO_OPENERS_TEXT: &str = "No openers found";
+pub const SOLVED_TEXT: &str = "Solved";
+pub const FAILED_TEXT: &str = "Failed";
+pub const DAILY_TEXT: &str = "Daily Results";
+pub const STATS_TEXT: &str = "Statistics";
+pub const SETTINGS_TEXT: &str = "Settings";
+pub const P1_WINS_TEXT: &str = "P1 Wins";
+pub const P2_WINS_TEXT: &str = "P2 Wins";
+pub const DRAW_TEXT: &str = "Draw";
+pub const CPU_WINS_TEXT: &str = "CPU Wins";
+pub const MISMATCH_TEXT: &str = "Mismatch: U to undo, Enter to retry";
+pub const UNDO_TEXT: &str = "U to undo";
+pub const EDITOR_TEXT: &str = "Editor";
+pub const EDITOR_HELP_TEXT: &str = "Mouse paint/erase  1-8 brush  IJLOSTZ queue  H hold  C clear  Ctrl+S/L save/load  Space play";
+
+#[cfg(target_arch = "wasm32")]
+pub const PUZZLE_DIR: &str = "/puzzles";
+#[cfg(not(target_arch = "wasm32"))]
+pub const PUZZLE_DIR: &str = "puzzles";
+#[cfg(target_arch = "wasm32")]
+pub const OPENER_DIR: &str = "/openers";
+#[cfg(not(target_arch = "wasm32"))]
+pub const OPENER_DIR: &str = "openers";
+#[cfg(target_arch = "wasm32")]
+pub const THEME_DIR: &str = "/themes";
+#[cfg(not(target_arch = "wasm32"))]
+pub const THEME_DIR: &str = "themes";
+pub const SAVE_DIR: &str = "save";
+pub const PUZZLE_SAVE: &str = "puzzles.txt";
+pub const OPENER_SAVE: &str = "openers.txt";
+pub const EDITOR_SAVE: &str = "position.txt";
+pub const DAILY_SAVE: &str = "daily.txt";
+pub const GAME_SAVE: &str = "game.json";
+pub const STATS_SAVE: &str = "stats.txt";
+pub const STATS_BACKUP: &str = "stats.txt.bak";
+pub const SETTINGS_SAVE: &str = "settings.txt";
+pub const STATS_DAYS: u64 = 14;
+pub const DAILY_SALT: u64 = 0x7e7205;
+
+pub const FADE_TIME: f64 = 4.0;
+pub const REVEAL_TIME: f64 = 1.0;
+pub const TARGET_ALPHA: f32 = 0.3;
+
+pub const EDITOR_BRUSHES: &str = "IJLOSTZG";
+
+pub const EMPTY_CELL: char = '.';
+pub const GARBAGE_CELL: char = 'G';
+pub const ACTIVE_CELL: char = '@';
+
+pub const MASTER_MAX_LEVEL: u32 = 999;
+pub const MASTER_GRAVITY: [(u32, u32); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4),
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, 256),
+    (300, 512),
+    (330, 768),
+    (360, 1024),
+    (400, 1280),
+    (420, 1024),
+    (450, 768),
+    (500, 5120),
+];
+// (level, are, lock delay, line clear delay) in frames
+pub const MASTER_DELAYS: [(u32, u32, u32, u32); 6] = [
+    (0, 25, 30, 40),
+    (500, 25, 30, 25),
+    (600, 25, 30, 16),
+    (700, 16, 30, 12),
+    (800, 12, 30, 6),
+    (900, 12, 17, 6),
+];
+pub const MASTER_GRADES: [(u32, &str); 18] = [
+    (0, "9"),
+    (400, "8"),
+    (800, "7"),
+    (1400, "6"),
+    (2000, "5"),
+    (3500, "4"),
+    (5500, "3"),
+    (8000, "2"),
+    (12000, "1"),
+    (16000, "S1"),
+    (22000, "S2"),
+    (30000, "S3"),
+    (40000, "S4"),
+    (52000, "S5"),
+    (66000, "S6"),
+    (82000, "S7"),
+    (100000, "S8"),
+    (120000, "S9"),
+];
+pub const MASTER_GM_SCORE: u32 = 126000;
+
+pub const ATTACK_LINES: [usize; 5] = [0, 0, 1, 2, 4];
+pub const ATTACK_TSPIN: [usize; 4] = [0, 2, 4, 6];
+pub const ATTACK_COMBO: [usize; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+pub const ATTACK_B2B: usize = 1;
+pub const ATTACK_PERFECT: usize = 10;
+
+pub const SURVIVAL_INTERVAL: f64 = 10.0;
+pub const SURVIVAL_MIN_INTERVAL: f64 = 1.5;
+pub const SURVIVAL_ACCELERATION: f64 = 0.95;
+
+pub const CLEAR_TYPES: [&str; 8] = [
+    "single",
+    "double",
+    "triple",
+    "tetris",
+    "tspin_single",
+    "tspin_double",
+    "tspin_triple",
+    "perfect",
+];
+pub const SIM_GAMES: u64 = 100;
+pub const SIM_MAX_PIECES: u32 = 1000;
+
+pub const BOT_SPEED: [f64; 4] = [0.75, 1.5, 2.5, 4.0];
+pub const BOT_DEPTH: [usize; 4] = [1, 1, 2, 2];
+pub const BOT_MISTAKES: [usize; 4] = [25, 10, 3, 0];
+pub const BOT_BUDGET: usize = 4;
+// Seconds an external bot may take to get ready or to answer a suggestion request
+pub const TBP_TIMEOUT: f64 = 5.0;
+
+// El-Tetris weights for Dellacherie's features, see https://imake.ninja/el-tetris-an-improvement-on-pierre-dellacheries-algorithm/
+pub const EVAL_LANDING_HEIGHT: f64 = -4.500158825082766;
+pub const EVAL_ERODED_CELLS: f64 = 3.4181268101392694;
+pub const EVAL_AGGREGATE_HEIGHT: f64 = 0.0;
+pub const EVAL_HOLES: f64 = -7.899265427351652;
+pub const EVAL_BUMPINESS: f64 = 0.0;
+pub const EVAL_ROW_TRANSITIONS: f64 = -3.2178882868487753;
+pub const EVAL_COLUMN_TRANSITIONS: f64 = -9.348695305445199;
+pub const EVAL_WELLS: f64 = -3.3855972247263626;
+pub const EVAL_LINES: f64 = 0.0;
+pub const WEIGHT_NAMES: [&str; 9] = [
+    "landing_height",
+    "eroded_cells",
+    "aggregate_height",
+    "holes",
+    "bumpiness",
+    "row_transitions",
+    "column_transitions",
+    "wells",
+    "lines",
+];
+
+pub const TRAIN_POPULATION: usize = 50;
+pub const TRAIN_GENERATIONS: u32 = 20;
+pub const TRAIN_GAMES: u64 = 4;
+pub const TRAIN_PIECES: u32 = 300;
+pub const TRAIN_TOURNAMENT: usize = 3;
+pub const TRAIN_ELITE: usize = 2;
+pub const TRAIN_MUTATION_RATE: f64 = 0.1;
+pub const TRAIN_MUTATION_SCALE: f64 = 0.2;
+pub const TRAIN_CHECKPOINT: &str = "train.txt";
+pub const TRAIN_OUTPUT: &str = "weights.txt";
+
+// Fumen v115, see https://github.com/knewjade/tetris-fumen
+pub const FUMEN_PREFIX: &str = "v115@";
+pub const FUMEN_TABLE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+pub const FUMEN_ASCII: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+pub const FUMEN_FIELD_TOP: usize = 23;
+pub const FUMEN_FIELD_BLOCKS: usize = (FUMEN_FIELD_TOP + 1) * GRID_WIDTH;
+pub const FUMEN_COMMENT_MAX: usize = 4095;
+pub const FUMEN_LINE_LENGTH: usize = 47;
+
+pub const TUI_WIDTH: usize = 80;
+pub const TUI_HEIGHT: usize = 24;
+pub const TUI_POLL: f64 = 0.005;
+pub const TUI_SIZE_POLL: f64 = 0.5;
+// Terminals report no key releases, so soft drop is held for a tap and then for as long as key repeats keep coming
+pub const TUI_TAP_TIME: f64 = 0.15;
+pub const TUI_REPEAT_DELAY: f64 = 0.6;
+pub const TUI_REPEAT_GAP: f64 = 0.1;
+// IJLOSTZ then garbage, two characters per cell
+pub const TUI_GLYPHS: [&str; 8] = ["==", "[]", "()", "::", "//", "/\\", "\\\\", "XX"];
+pub const TUI_SPARK: &str = " ▁▂▃▄▅▆▇█";
+pub const TUI_WELCOME_HELP_TEXT: &str = "A/D mode  H option  V stack  C continue  S stats  O settings  Esc quit";
+pub const TUI_TOO_SMALL_TEXT: &str = "Terminal too small:";
+pub const TUI_SETTINGS_HELP_TEXT: &str = "W/S select  A/D change  Enter back  Esc quit";
+pub const TUI_EDITOR_HELP_TEXT: &str = "Arrows move  P paint  X erase  1-8 brush  C clear  Space play";
+pub const TUI_QUEUE_HELP_TEXT: &str = "IJLOSTZ queue  Backspace remove  H hold  Ctrl+S/L save/load  Enter back";
 
 pub const BACKGROUND_SOUND_PARAMS: PlaySoundParams = PlaySoundParams {
     looped: true,
@@ -55,4 +283,4 @@ pub const BACKGROUND_SOUND_PARAMS: PlaySoundParams = PlaySoundParams {
 pub const SOUND_PARAMS: PlaySoundParams = PlaySoundParams {
     looped: false,
     volume: 1.0,
-};
\ No newline at end of file
+};
@@ -0,0 +1,70 @@
+use crate::constants::*;
+use crate::save::*;
+use macroquad::miniquad::date;
+
+pub struct DailyRecord {
+    pub day: u64,
+    pub score: u32,
+}
+
+pub fn today() -> u64 {
+    (date::now() / 86400.0) as u64
+}
+
+pub fn seed(day: u64) -> u64 {
+    day.wrapping_mul(0x9e3779b97f4a7c15) ^ DAILY_SALT
+}
+
+pub fn date_string(day: u64) -> String {
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = day as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+pub fn load_history() -> Vec<DailyRecord> {
+    load_lines(DAILY_SAVE)
+        .iter()
+        .filter_map(|line| {
+            let (day, score) = line.split_once(' ')?;
+            Some(DailyRecord {
+                day: day.parse().ok()?,
+                score: score.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+pub fn save_history(history: &[DailyRecord]) {
+    let lines: Vec<String> = history
+        .iter()
+        .map(|record| format!("{} {}", record.day, record.score))
+        .collect();
+    save_lines(DAILY_SAVE, &lines);
+}
+
+// Records today's attempt if it is the first, returning the day the game counts for.
+pub fn start_daily(history: &mut Vec<DailyRecord>) -> Option<u64> {
+    let day = today();
+    if history.iter().any(|r| r.day == day) {
+        return None;
+    }
+    history.push(DailyRecord { day, score: 0 });
+    save_history(history);
+    Some(day)
+}
+
+// The day is the one the game started on, which may no longer be today.
+pub fn finish_daily(history: &mut [DailyRecord], day: u64, score: u32) {
+    if let Some(record) = history.iter_mut().find(|r| r.day == day) {
+        record.score = score;
+    }
+    save_history(history);
+}
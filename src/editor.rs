@@ -0,0 +1,225 @@
+use crate::board::*;
+use crate::constants::*;
+use crate::fumen::*;
+use crate::tetrus::*;
+
+pub struct Editor {
+    board: Vec<Block>,
+    queue: Vec<BlockType>,
+    hold: Option<BlockType>,
+    comment: String,
+    brush: usize,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor {
+            board: Vec::new(),
+            queue: Vec::new(),
+            hold: None,
+            comment: String::new(),
+            brush: 0,
+        }
+    }
+
+    // The queue and hold are read from a quiz comment, or else the queue is the page's piece.
+    pub fn from_page(page: &Page) -> Self {
+        let comment = page.comment.lines().collect::<Vec<_>>().join(" ");
+        let (hold, queue, comment) = match parse_quiz(&comment) {
+            Some(quiz) => quiz,
+            None => (None, page.get_block_id().into_iter().collect(), comment),
+        };
+        Editor {
+            board: page.field.clone(),
+            queue,
+            hold,
+            comment,
+            brush: 0,
+        }
+    }
+
+    pub fn to_page(&self) -> Page {
+        let comment = match self.hold.is_some() || !self.queue.is_empty() {
+            true => quiz_comment(self.hold.as_ref(), &self.queue, &self.comment),
+            false => self.comment.clone(),
+        };
+        Page {
+            field: self.board.clone(),
+            piece: Vec::new(),
+            comment,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Editor, String> {
+        let mut queue = Vec::new();
+        let mut hold = None;
+        let mut comment = String::new();
+        let mut rows: Vec<&str> = Vec::new();
+        let mut in_board = false;
+
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if in_board {
+                rows.push(line);
+                continue;
+            }
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("expected `key: value`, found: {}", line)),
+            };
+            match key {
+                "queue" => {
+                    for c in value.chars().filter(|c| !c.is_whitespace()) {
+                        match BlockType::from_char(c) {
+                            Some(id) => queue.push(id),
+                            None => return Err(format!("invalid piece in queue: {}", c)),
+                        }
+                    }
+                }
+                "hold" => {
+                    let mut chars = value.chars();
+                    hold = match (chars.next(), chars.next()) {
+                        (Some(c), None) => BlockType::from_char(c),
+                        _ => None,
+                    };
+                    if hold.is_none() && !value.is_empty() {
+                        return Err(format!("invalid hold piece: {}", value));
+                    }
+                }
+                "comment" => comment = String::from(value),
+                "board" => in_board = true,
+                _ => return Err(format!("unknown key: {}", key)),
+            }
+        }
+
+        Ok(Editor {
+            board: parse_board(&rows)?,
+            queue,
+            hold,
+            comment,
+            brush: 0,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let queue: String = self.queue.iter().map(|id| id.to_char()).collect();
+        let mut lines = vec![format!("queue: {}", queue)];
+        if let Some(id) = &self.hold {
+            lines.push(format!("hold: {}", id.to_char()));
+        }
+        if !self.comment.is_empty() {
+            lines.push(format!("comment: {}", self.comment));
+        }
+        lines.push(String::from("board:"));
+        lines.extend(format_board(&self.board, &[]));
+        lines.join("\n")
+    }
+
+    pub fn paint(&mut self, x: usize, y: usize) {
+        let color = EDITOR_BRUSHES
+            .chars()
+            .nth(self.brush)
+            .and_then(BlockType::from_char)
+            .map_or(GREY, |id| id.get_color());
+        self.erase(x, y);
+        self.board.push(Block {
+            position: Position::new((x, y)),
+            color,
+            locked_at: 0,
+        });
+    }
+
+    pub fn erase(&mut self, x: usize, y: usize) {
+        self.board.retain(|n| n.position != Position::new((x, y)));
+    }
+
+    pub fn clear(&mut self) {
+        self.board.clear();
+        self.queue.clear();
+        self.hold = None;
+    }
+
+    pub fn set_brush(&mut self, brush: usize) {
+        self.brush = brush.min(EDITOR_BRUSHES.len() - 1);
+    }
+
+    pub fn get_brush(&self) -> usize {
+        self.brush
+    }
+
+    pub fn push_queue(&mut self, id: BlockType) {
+        self.queue.push(id);
+    }
+
+    pub fn pop_queue(&mut self) {
+        self.queue.pop();
+    }
+
+    // Steps the hold through empty and each piece in `IJLOSTZ` order.
+    pub fn cycle_hold(&mut self) {
+        let pieces: Vec<BlockType> = "IJLOSTZ".chars().filter_map(BlockType::from_char).collect();
+        self.hold = match self
+            .hold
+            .as_ref()
+            .and_then(|id| pieces.iter().position(|p| p == id))
+        {
+            Some(i) => pieces.get(i + 1).cloned(),
+            None => pieces.first().cloned(),
+        };
+    }
+
+    pub fn get_board(&self) -> &[Block] {
+        &self.board
+    }
+
+    pub fn get_queue(&self) -> &[BlockType] {
+        &self.queue
+    }
+
+    pub fn get_hold(&self) -> Option<&BlockType> {
+        self.hold.as_ref()
+    }
+
+    pub fn get_comment(&self) -> &str {
+        &self.comment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION: &str = "queue: TIO\nhold: S\ncomment: practice\nboard:\nGGGGG.GGGG";
+
+    #[test]
+    fn text_round_trip_keeps_the_hold() {
+        let editor = Editor::parse(POSITION).unwrap();
+        assert!(editor.get_hold() == Some(&BlockType::S));
+        assert_eq!(editor.to_text(), POSITION);
+        assert!(Editor::parse("hold: X\nboard:").is_err());
+    }
+
+    #[test]
+    fn fumen_round_trip_keeps_the_hold() {
+        let editor = Editor::parse(POSITION).unwrap();
+        let pages = decode(&encode(&[editor.to_page()])).unwrap();
+        let decoded = Editor::from_page(&pages[0]);
+        assert_eq!(decoded.to_text(), POSITION);
+    }
+
+    #[test]
+    fn hold_cycles_back_to_empty() {
+        let mut editor = Editor::new();
+        for _ in 0..7 {
+            editor.cycle_hold();
+            assert!(editor.get_hold().is_some());
+        }
+        editor.cycle_hold();
+        assert!(editor.get_hold().is_none());
+    }
+}
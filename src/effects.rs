@@ -0,0 +1,343 @@
+use crate::constants::*;
+use crate::layout::*;
+use crate::tetrus::*;
+use crate::theme::*;
+use macroquad::prelude::*;
+use macroquad::rand::gen_range;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Effect {
+    Clear,
+    Particles,
+    Lock,
+    Trail,
+    Shake,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 5] = [
+        Effect::Clear,
+        Effect::Particles,
+        Effect::Lock,
+        Effect::Trail,
+        Effect::Shake,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Effect::Clear => "Line clears",
+            Effect::Particles => "Particles",
+            Effect::Lock => "Lock flash",
+            Effect::Trail => "Drop trail",
+            Effect::Shake => "Screen shake",
+        }
+    }
+
+    // Key in `settings.txt`
+    pub fn key(&self) -> &str {
+        match self {
+            Effect::Clear => "clear_effect",
+            Effect::Particles => "particles",
+            Effect::Lock => "lock_flash",
+            Effect::Trail => "drop_trail",
+            Effect::Shake => "screen_shake",
+        }
+    }
+}
+
+struct Particle {
+    // In cells from the top left of the grid, hidden rows included
+    position: Vec2,
+    velocity: Vec2,
+    color: Color,
+    life: u32,
+}
+
+struct Clearing {
+    rows: Vec<usize>,
+    cells: Vec<Block>,
+    // Rows each post-collapse row moved down by
+    shifts: [usize; GRID_HEIGHT],
+    // Frame the rows start collapsing on screen, None while the engine still holds them
+    collapse: Option<u64>,
+}
+
+struct Flash {
+    cells: Vec<Block>,
+    frame: u64,
+}
+
+struct Trail {
+    // Column, top row before and top row after the drop
+    columns: Vec<(usize, usize, usize)>,
+    color: Color,
+    frame: u64,
+}
+
+// Visual feedback driven by engine events. Nothing here feeds back into the game, so turning an
+// effect off never changes timing.
+pub struct Effects {
+    pub enabled: [bool; 5],
+    frame: u64,
+    clearing: Option<Clearing>,
+    particles: Vec<Particle>,
+    flash: Option<Flash>,
+    trail: Option<Trail>,
+    shake: Option<u64>,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self::new([true; 5])
+    }
+}
+
+impl Effects {
+    pub fn new(enabled: [bool; 5]) -> Self {
+        Effects {
+            enabled,
+            frame: 0,
+            clearing: None,
+            particles: Vec::new(),
+            flash: None,
+            trail: None,
+            shake: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Effects::new(self.enabled);
+    }
+
+    fn is_enabled(&self, effect: Effect) -> bool {
+        self.enabled[effect as usize]
+    }
+
+    pub fn update(&mut self, tetrus: &Tetrus, events: &[Event]) {
+        self.frame = tetrus.get_frame();
+        let held = !tetrus.get_clearing().is_empty();
+        if let Some(clearing) = &mut self.clearing {
+            if clearing.collapse.is_none() && !held {
+                clearing.collapse = Some(self.frame);
+            }
+        }
+        for event in events {
+            match event {
+                Event::Lock => {
+                    // Offsets from an earlier clear no longer apply to the board
+                    self.clearing = None;
+                    self.flash = self.is_enabled(Effect::Lock).then(|| Flash {
+                        cells: tetrus.get_last_piece().to_vec(),
+                        frame: self.frame,
+                    })
+                }
+                Event::Clear(clear) => self.on_clear(tetrus, clear.lines, held),
+                Event::Spawn | Event::Hold => (),
+            }
+        }
+        for particle in &mut self.particles {
+            particle.velocity.y += EFFECT_PARTICLE_GRAVITY;
+            particle.position += particle.velocity;
+            particle.life -= 1;
+        }
+        self.particles.retain(|p| p.life > 0);
+    }
+
+    fn on_clear(&mut self, tetrus: &Tetrus, lines: usize, held: bool) {
+        let board: Vec<&Block> = tetrus
+            .get_last_board()
+            .iter()
+            .chain(tetrus.get_last_piece())
+            .collect();
+        let rows: Vec<usize> = (HIDDEN_ROWS..GRID_HEIGHT)
+            .filter(|y| board.iter().filter(|b| b.position.y == *y).count() == GRID_WIDTH)
+            .collect();
+        let cells: Vec<Block> = board
+            .into_iter()
+            .filter(|b| rows.contains(&b.position.y))
+            .cloned()
+            .collect();
+        let mut shifts = [0; GRID_HEIGHT];
+        for y in (0..GRID_HEIGHT).filter(|y| !rows.contains(y)) {
+            let shift = rows.iter().filter(|r| **r > y).count();
+            shifts[y + shift] = shift;
+        }
+        if self.is_enabled(Effect::Particles) {
+            for cell in &cells {
+                for _ in 0..EFFECT_PARTICLES_PER_CELL {
+                    self.particles.push(Particle {
+                        position: vec2(cell.position.x as f32, cell.position.y as f32)
+                            + vec2(gen_range(0.0, 1.0), gen_range(0.0, 1.0)),
+                        velocity: vec2(gen_range(-0.08, 0.08), gen_range(-0.2, -0.02)),
+                        color: cell.color,
+                        life: EFFECT_PARTICLE_FRAMES - gen_range(0, EFFECT_PARTICLE_FRAMES / 2),
+                    });
+                }
+            }
+        }
+        if lines >= 4 && self.is_enabled(Effect::Shake) {
+            self.shake = Some(self.frame);
+        }
+        // Without an engine clear delay the rows are already gone, so the flash plays before the
+        // collapse is shown
+        let flash = match self.is_enabled(Effect::Clear) {
+            true => EFFECT_FLASH_FRAMES,
+            false => 0,
+        };
+        self.clearing = Some(Clearing {
+            rows,
+            cells,
+            shifts,
+            collapse: (!held).then_some(self.frame + flash),
+        });
+    }
+
+    pub fn on_drop(&mut self, before: &[Block], after: &[Block], frame: u64) {
+        if !self.is_enabled(Effect::Trail) {
+            return;
+        }
+        let top = |blocks: &[Block], x: usize| {
+            blocks
+                .iter()
+                .filter(|b| b.position.x == x)
+                .map(|b| b.position.y)
+                .min()
+        };
+        let mut columns = Vec::new();
+        for x in 0..GRID_WIDTH {
+            if let (Some(from), Some(to)) = (top(before, x), top(after, x)) {
+                columns.push((x, from, to));
+            }
+        }
+        self.trail = Some(Trail {
+            columns,
+            color: before.first().map_or(WHITE, |b| b.color),
+            frame,
+        });
+    }
+
+    // How far the collapse has played, from 0 with the rows still in place to 1 once settled.
+    fn progress(&self, clearing: &Clearing) -> f32 {
+        match clearing.collapse {
+            None => 0.0,
+            Some(start) if self.frame < start => 0.0,
+            Some(_) if !self.is_enabled(Effect::Clear) => 1.0,
+            Some(start) => ((self.frame - start) as f32 / EFFECT_COLLAPSE_FRAMES as f32).min(1.0),
+        }
+    }
+
+    // Vertical offset in cells for a settled block on `row` while the rows above a clear fall.
+    pub fn row_offset(&self, row: usize) -> f32 {
+        match &self.clearing {
+            Some(clearing) if clearing.collapse.is_some() => {
+                -(clearing.shifts[row] as f32) * (1.0 - self.progress(clearing))
+            }
+            _ => 0.0,
+        }
+    }
+
+    // Screen offset in pixels for the current frame.
+    pub fn shake(&self, cell: f32) -> Vec2 {
+        let start = match self.shake {
+            Some(start) if self.frame < start + EFFECT_SHAKE_FRAMES => start,
+            _ => return Vec2::ZERO,
+        };
+        let t = self.frame.saturating_sub(start) as f32 / EFFECT_SHAKE_FRAMES as f32;
+        let amplitude = cell * EFFECT_SHAKE_AMPLITUDE * (1.0 - t);
+        let phase = self.frame as f32;
+        vec2((phase * 2.1).sin(), (phase * 1.7).cos()) * amplitude
+    }
+
+    pub fn draw(&self, well: Rect, theme: &Theme) {
+        let cell = well.w / GRID_WIDTH as f32;
+        let visible = |y: usize| y >= HIDDEN_ROWS;
+
+        if let Some(trail) = &self.trail {
+            let t = self.frame.saturating_sub(trail.frame) as f32 / EFFECT_TRAIL_FRAMES as f32;
+            if t < 1.0 {
+                let color = Color {
+                    a: 0.35 * (1.0 - t),
+                    ..theme.block_color(trail.color)
+                };
+                for &(x, from, to) in &trail.columns {
+                    let from = from.max(HIDDEN_ROWS);
+                    if to > from {
+                        let top = cell_rect(well, x, from);
+                        draw_rectangle(top.x, top.y, cell, (to - from) as f32 * cell, color);
+                    }
+                }
+            }
+        }
+
+        if let Some(clearing) = &self.clearing {
+            let progress = self.progress(clearing);
+            let flashing = match clearing.collapse {
+                Some(start) => self.frame < start,
+                None => true,
+            };
+            if self.is_enabled(Effect::Clear) && progress < 1.0 {
+                // Cleared rows that are no longer on the board are drawn here until they collapse
+                if clearing.collapse.is_some() {
+                    for block in clearing.cells.iter().filter(|b| visible(b.position.y)) {
+                        let rect = cell_rect(well, block.position.x, block.position.y);
+                        let h = rect.h * (1.0 - progress);
+                        let rect = Rect::new(rect.x, rect.y + (rect.h - h) / 2.0, rect.w, h);
+                        theme.draw_block(rect, block.color, 1.0 - progress);
+                    }
+                }
+                if flashing {
+                    let blink = if self.frame % 4 < 2 { 0.8 } else { 0.4 };
+                    for &row in clearing.rows.iter().filter(|y| visible(**y)) {
+                        let rect = cell_rect(well, 0, row);
+                        draw_rectangle(
+                            rect.x,
+                            rect.y,
+                            well.w,
+                            cell,
+                            Color::new(1.0, 1.0, 1.0, blink),
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(flash) = &self.flash {
+            let t = self.frame.saturating_sub(flash.frame) as f32 / EFFECT_LOCK_FRAMES as f32;
+            if t < 1.0 {
+                let color = Color::new(1.0, 1.0, 1.0, 0.6 * (1.0 - t));
+                for block in &flash.cells {
+                    let y = block.position.y;
+                    let offset = match &self.clearing {
+                        Some(clearing) if clearing.rows.contains(&y) => continue,
+                        Some(clearing) => {
+                            let shift = clearing.rows.iter().filter(|r| **r > y).count();
+                            shift as f32 * self.progress(clearing)
+                        }
+                        None => 0.0,
+                    };
+                    let rect = cell_rect(well, block.position.x, y);
+                    let rect = Rect {
+                        y: rect.y + offset * cell,
+                        ..rect
+                    };
+                    if rect.y >= well.y {
+                        draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+                    }
+                }
+            }
+        }
+
+        for particle in &self.particles {
+            let size = cell * 0.2 * particle.life as f32 / EFFECT_PARTICLE_FRAMES as f32;
+            let x = well.x + particle.position.x * cell;
+            let y = well.y + (particle.position.y - HIDDEN_ROWS as f32) * cell;
+            draw_rectangle(
+                x - size / 2.0,
+                y - size / 2.0,
+                size,
+                size,
+                theme.block_color(particle.color),
+            );
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use crate::constants::*;
+use crate::tetrus::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub const TOP_OUT_SCORE: f64 = -1e9;
+
+#[derive(Clone, Copy)]
+pub struct Weights {
+    pub landing_height: f64,
+    pub eroded_cells: f64,
+    pub aggregate_height: f64,
+    pub holes: f64,
+    pub bumpiness: f64,
+    pub row_transitions: f64,
+    pub column_transitions: f64,
+    pub wells: f64,
+    pub lines: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            landing_height: EVAL_LANDING_HEIGHT,
+            eroded_cells: EVAL_ERODED_CELLS,
+            aggregate_height: EVAL_AGGREGATE_HEIGHT,
+            holes: EVAL_HOLES,
+            bumpiness: EVAL_BUMPINESS,
+            row_transitions: EVAL_ROW_TRANSITIONS,
+            column_transitions: EVAL_COLUMN_TRANSITIONS,
+            wells: EVAL_WELLS,
+            lines: EVAL_LINES,
+        }
+    }
+}
+
+impl Weights {
+    pub fn to_array(&self) -> [f64; WEIGHT_NAMES.len()] {
+        [
+            self.landing_height,
+            self.eroded_cells,
+            self.aggregate_height,
+            self.holes,
+            self.bumpiness,
+            self.row_transitions,
+            self.column_transitions,
+            self.wells,
+            self.lines,
+        ]
+    }
+
+    pub fn from_array(values: [f64; WEIGHT_NAMES.len()]) -> Self {
+        Weights {
+            landing_height: values[0],
+            eroded_cells: values[1],
+            aggregate_height: values[2],
+            holes: values[3],
+            bumpiness: values[4],
+            row_transitions: values[5],
+            column_transitions: values[6],
+            wells: values[7],
+            lines: values[8],
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Weights, String> {
+        let mut values = Weights::default().to_array();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (name, value) = line
+                .split_once(' ')
+                .ok_or(format!("expected `name value`, found: {}", line))?;
+            let index = WEIGHT_NAMES
+                .iter()
+                .position(|n| *n == name)
+                .ok_or(format!("unknown weight: {}", name))?;
+            values[index] = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight: {}", value))?;
+        }
+        Ok(Weights::from_array(values))
+    }
+
+    pub fn to_text(&self) -> String {
+        WEIGHT_NAMES
+            .iter()
+            .zip(self.to_array())
+            .map(|(name, value)| format!("{} {}\n", name, value))
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct Placement {
+    pub moves: Vec<Movement>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Clone)]
+struct Piece {
+    blocks: Vec<Block>,
+    origin: Position,
+    moves: Vec<Movement>,
+}
+
+impl Piece {
+    fn key(&self) -> (Vec<(usize, usize)>, (usize, usize)) {
+        let mut cells: Vec<(usize, usize)> = self
+            .blocks
+            .iter()
+            .map(|n| (n.position.x, n.position.y))
+            .collect();
+        cells.sort();
+        (cells, (self.origin.x, self.origin.y))
+    }
+
+    fn apply(&self, movement: Movement, id: &BlockType, inactive: &[Block]) -> Option<Piece> {
+        let mut piece = self.clone();
+        match movement {
+            Movement::Left | Movement::Right => {
+                let collision = match movement {
+                    Movement::Left => Collision::Left,
+                    _ => Collision::Right,
+                };
+                if collides(&piece.blocks, inactive, collision) {
+                    return None;
+                }
+                for block in &mut piece.blocks {
+                    match movement {
+                        Movement::Left => block.position.x -= 1,
+                        _ => block.position.x += 1,
+                    }
+                }
+                match movement {
+                    Movement::Left => piece.origin.x -= 1,
+                    _ => piece.origin.x += 1,
+                }
+            }
+            Movement::Drop => {
+                if collides(&piece.blocks, inactive, Collision::Down) {
+                    return None;
+                }
+                while !collides(&piece.blocks, inactive, Collision::Down) {
+                    for block in &mut piece.blocks {
+                        block.position.y += 1;
+                    }
+                    piece.origin.y += 1;
+                }
+            }
+            Movement::Rotate => {
+                if *id == BlockType::O {
+                    return None;
+                }
+                piece.blocks = rotate(&piece.blocks, &piece.origin, inactive)?;
+            }
+        }
+        piece.moves.push(movement);
+        Some(piece)
+    }
+}
+
+pub fn spawn(id: &BlockType) -> (Vec<Block>, Position) {
+    let blocks = id
+        .get_blocks()
+        .iter()
+        .map(|block| Block {
+            position: Position::new(*block),
+            color: id.get_color(),
+            locked_at: 0,
+        })
+        .collect();
+    (blocks, Position::new(id.get_origin()))
+}
+
+pub fn placements(
+    active: &[Block],
+    origin: &Position,
+    id: &BlockType,
+    inactive: &[Block],
+) -> Vec<Placement> {
+    let start = Piece {
+        blocks: active.to_vec(),
+        origin: origin.clone(),
+        moves: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut landed: HashMap<_, usize> = HashMap::new();
+    let mut placements: Vec<Placement> = Vec::new();
+    let mut frontier = VecDeque::new();
+    seen.insert(start.key());
+    frontier.push_back(start);
+
+    while let Some(piece) = frontier.pop_front() {
+        let mut last = piece.clone();
+        if !collides(&last.blocks, inactive, Collision::Down) {
+            last = match last.apply(Movement::Drop, id, inactive) {
+                Some(dropped) => dropped,
+                None => continue,
+            };
+        }
+        let cells = last.key().0;
+        let mut moves = last.moves;
+        if moves.last() != Some(&Movement::Drop) {
+            moves.push(Movement::Drop);
+        }
+        let placement = Placement {
+            moves,
+            blocks: last.blocks,
+        };
+        match landed.get(&cells) {
+            Some(&i) if placement.moves.len() < placements[i].moves.len() => {
+                placements[i] = placement;
+            }
+            Some(_) => (),
+            None => {
+                landed.insert(cells, placements.len());
+                placements.push(placement);
+            }
+        }
+        for movement in [
+            Movement::Left,
+            Movement::Right,
+            Movement::Rotate,
+            Movement::Drop,
+        ] {
+            if let Some(next) = piece.apply(movement, id, inactive) {
+                if seen.insert(next.key()) {
+                    frontier.push_back(next);
+                }
+            }
+        }
+    }
+    placements
+}
+
+pub fn place(inactive: &[Block], blocks: &[Block]) -> (Vec<Block>, usize, usize) {
+    let mut board: Vec<Block> = inactive.iter().chain(blocks).cloned().collect();
+    let full: Vec<usize> = (4..GRID_HEIGHT)
+        .filter(|y| board.iter().filter(|n| n.position.y == *y).count() == GRID_WIDTH)
+        .collect();
+    let eroded = blocks
+        .iter()
+        .filter(|n| full.contains(&n.position.y))
+        .count();
+    board.retain(|n| !full.contains(&n.position.y));
+    for block in &mut board {
+        block.position.y += full.iter().filter(|y| **y > block.position.y).count();
+    }
+    (board, full.len(), eroded * full.len())
+}
+
+pub fn evaluate(
+    board: &[Block],
+    blocks: &[Block],
+    lines: usize,
+    eroded: usize,
+    weights: &Weights,
+) -> f64 {
+    if board.iter().any(|n| n.position.y <= 3) {
+        return TOP_OUT_SCORE;
+    }
+    let mut grid = [[false; GRID_WIDTH]; GRID_HEIGHT];
+    for block in board {
+        grid[block.position.y][block.position.x] = true;
+    }
+    let filled = |x: i32, y: usize| x < 0 || x >= GRID_WIDTH as i32 || grid[y][x as usize];
+
+    let mut heights = [0; GRID_WIDTH];
+    let mut holes = 0;
+    let mut column_transitions = 0;
+    let mut wells = 0;
+    for (x, height) in heights.iter_mut().enumerate() {
+        let mut covered = false;
+        let mut above = false;
+        let mut depth = 0;
+        for (y, row) in grid.iter().enumerate().skip(4) {
+            let cell = row[x];
+            if cell && !covered {
+                covered = true;
+                *height = GRID_HEIGHT - y;
+            }
+            if !cell && covered {
+                holes += 1;
+            }
+            if cell != above {
+                column_transitions += 1;
+            }
+            above = cell;
+            if !cell && filled(x as i32 - 1, y) && filled(x as i32 + 1, y) {
+                depth += 1;
+                wells += depth;
+            } else {
+                depth = 0;
+            }
+        }
+        if !above {
+            column_transitions += 1;
+        }
+    }
+
+    let mut row_transitions = 0;
+    for y in 4..GRID_HEIGHT {
+        for x in 0..=GRID_WIDTH as i32 {
+            if filled(x - 1, y) != filled(x, y) {
+                row_transitions += 1;
+            }
+        }
+    }
+
+    let aggregate_height: usize = heights.iter().sum();
+    let bumpiness: usize = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+    let top = blocks.iter().map(|n| GRID_HEIGHT - n.position.y).max();
+    let bottom = blocks.iter().map(|n| GRID_HEIGHT - n.position.y).min();
+    let landing_height = (top.unwrap_or(0) + bottom.unwrap_or(0)) as f64 / 2.0;
+
+    weights.landing_height * landing_height
+        + weights.eroded_cells * eroded as f64
+        + weights.aggregate_height * aggregate_height as f64
+        + weights.holes * holes as f64
+        + weights.bumpiness * bumpiness as f64
+        + weights.row_transitions * row_transitions as f64
+        + weights.column_transitions * column_transitions as f64
+        + weights.wells * wells as f64
+        + weights.lines * lines as f64
+}
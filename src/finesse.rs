@@ -0,0 +1,176 @@
+use crate::constants::*;
+use crate::eval::*;
+use crate::tetrus::*;
+
+fn cells(blocks: &[Block]) -> Vec<(usize, usize)> {
+    let mut cells: Vec<(usize, usize)> = blocks
+        .iter()
+        .map(|n| (n.position.x, n.position.y))
+        .collect();
+    cells.sort();
+    cells
+}
+
+#[derive(Clone)]
+pub struct FinesseResult {
+    pub id: BlockType,
+    pub presses: usize,
+    pub minimum: usize,
+}
+
+impl FinesseResult {
+    pub fn is_fault(&self) -> bool {
+        self.presses > self.minimum
+    }
+}
+
+#[derive(Clone)]
+pub struct Finesse {
+    shortest: Vec<(Vec<(usize, usize)>, usize)>,
+    presses: usize,
+    dropped: bool,
+    pieces: u32,
+    faults: u32,
+    by_piece: [u32; 7],
+    by_column: [u32; GRID_WIDTH],
+    last: Option<FinesseResult>,
+}
+
+impl Default for Finesse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Finesse {
+    pub fn new() -> Self {
+        Finesse {
+            shortest: Vec::new(),
+            presses: 0,
+            dropped: false,
+            pieces: 0,
+            faults: 0,
+            by_piece: [0; 7],
+            by_column: [0; GRID_WIDTH],
+            last: None,
+        }
+    }
+
+    pub fn on_press(&mut self) {
+        self.presses += 1;
+    }
+
+    // A hard or soft drop press finishes the piece the way the shortest move lists do.
+    pub fn on_drop(&mut self) {
+        self.dropped = true;
+    }
+
+    // Called as the piece spawns, before any input for it.
+    pub fn on_spawn(&mut self, tetrus: &Tetrus) {
+        self.presses = 0;
+        self.dropped = false;
+        self.shortest = placements(
+            &tetrus.active,
+            tetrus.get_origin(),
+            tetrus.get_block_id(),
+            &tetrus.inactive,
+        )
+        .into_iter()
+        .map(|p| (cells(&p.blocks), p.moves.len()))
+        .collect();
+    }
+
+    pub fn on_lock(&mut self, tetrus: &Tetrus) {
+        let placed = cells(tetrus.get_last_piece());
+        // The move lists end in a drop, which a piece left to lock under gravity never pressed
+        let minimum = match self.shortest.iter().find(|(c, _)| *c == placed) {
+            Some((_, moves)) if self.dropped => *moves,
+            Some((_, moves)) => moves - 1,
+            None => return,
+        };
+        let result = FinesseResult {
+            id: tetrus.get_block_id().clone(),
+            presses: self.presses,
+            minimum,
+        };
+        self.pieces += 1;
+        if result.is_fault() {
+            self.faults += 1;
+            self.by_piece[result.id.index()] += 1;
+            if let Some((column, _)) = placed.first() {
+                self.by_column[*column] += 1;
+            }
+        }
+        self.last = Some(result);
+    }
+
+    pub fn get_last(&self) -> Option<&FinesseResult> {
+        self.last.as_ref()
+    }
+
+    pub fn get_pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    pub fn get_faults(&self) -> u32 {
+        self.faults
+    }
+
+    pub fn get_by_piece(&self) -> &[u32; 7] {
+        &self.by_piece
+    }
+
+    pub fn get_by_column(&self) -> &[u32; GRID_WIDTH] {
+        &self.by_column
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_until(tetrus: &mut Tetrus, event: Event) {
+        for _ in 0..10000 {
+            tetrus.update();
+            if tetrus.take_events().contains(&event) {
+                return;
+            }
+        }
+        panic!("event never happened");
+    }
+
+    fn spawn_o() -> (Finesse, Tetrus) {
+        let mut finesse = Finesse::new();
+        let mut tetrus = Tetrus::new();
+        tetrus.set_queue(vec![BlockType::O; 3]);
+        run_until(&mut tetrus, Event::Spawn);
+        finesse.on_spawn(&tetrus);
+        (finesse, tetrus)
+    }
+
+    #[test]
+    fn gravity_lock_is_measured_without_the_drop() {
+        let (mut finesse, mut tetrus) = spawn_o();
+        // An O does not rotate, so the press is wasted
+        finesse.on_press();
+        tetrus.player_move(Movement::Rotate);
+        run_until(&mut tetrus, Event::Lock);
+        finesse.on_lock(&tetrus);
+        let result = finesse.get_last().unwrap();
+        assert_eq!((result.presses, result.minimum), (1, 0));
+        assert!(result.is_fault());
+    }
+
+    #[test]
+    fn hard_drop_is_measured_with_the_drop() {
+        let (mut finesse, mut tetrus) = spawn_o();
+        finesse.on_press();
+        finesse.on_drop();
+        tetrus.player_move(Movement::Drop);
+        run_until(&mut tetrus, Event::Lock);
+        finesse.on_lock(&tetrus);
+        let result = finesse.get_last().unwrap();
+        assert_eq!((result.presses, result.minimum), (1, 1));
+        assert!(!result.is_fault());
+    }
+}
@@ -0,0 +1,651 @@
+use crate::constants::*;
+use crate::tetrus::*;
+use macroquad::prelude::Color;
+
+type Field = [u8; FUMEN_FIELD_BLOCKS];
+
+const ROTATIONS: [u32; 4] = [2, 1, 0, 3];
+
+fn to_piece(id: &BlockType) -> u8 {
+    match id {
+        BlockType::I => 1,
+        BlockType::L => 2,
+        BlockType::O => 3,
+        BlockType::Z => 4,
+        BlockType::T => 5,
+        BlockType::J => 6,
+        BlockType::S => 7,
+    }
+}
+
+fn from_piece(piece: u8) -> Option<BlockType> {
+    match piece {
+        1 => Some(BlockType::I),
+        2 => Some(BlockType::L),
+        3 => Some(BlockType::O),
+        4 => Some(BlockType::Z),
+        5 => Some(BlockType::T),
+        6 => Some(BlockType::J),
+        7 => Some(BlockType::S),
+        _ => None,
+    }
+}
+
+fn to_color(piece: u8) -> Color {
+    from_piece(piece).map_or(GREY, |id| id.get_color())
+}
+
+fn from_color(color: Color) -> u8 {
+    BlockType::from_color(color).map_or(8, |id| to_piece(&id))
+}
+
+// Cells relative to the piece center with y pointing up, rotated clockwise `rotation` times.
+fn shape(id: &BlockType, rotation: u32) -> [(i32, i32); 4] {
+    let cells = match id {
+        BlockType::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        BlockType::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        BlockType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        BlockType::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        BlockType::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        BlockType::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        BlockType::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    cells.map(|(x, y)| match rotation {
+        1 => (y, -x),
+        2 => (-x, -y),
+        3 => (-y, x),
+        _ => (x, y),
+    })
+}
+
+// Fumen stores some pieces by a corner instead of their rotation center.
+fn correction(id: &BlockType, rotation: u32) -> (i32, i32) {
+    match (id, rotation) {
+        (BlockType::O, 0) => (0, -1),
+        (BlockType::O, 2) => (1, 0),
+        (BlockType::O, 3) => (1, -1),
+        (BlockType::I, 2) => (1, 0),
+        (BlockType::I, 3) => (0, -1),
+        (BlockType::S, 0) => (0, -1),
+        (BlockType::S, 1) => (-1, 0),
+        (BlockType::Z, 0) => (0, -1),
+        (BlockType::Z, 3) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+fn field_index(x: i32, y: i32) -> Option<usize> {
+    match x >= 0 && x < GRID_WIDTH as i32 && y >= 0 && y < FUMEN_FIELD_TOP as i32 {
+        true => Some((FUMEN_FIELD_TOP - 1 - y as usize) * GRID_WIDTH + x as usize),
+        false => None,
+    }
+}
+
+fn clear_lines(field: &mut Field) {
+    let rows: Vec<[u8; GRID_WIDTH]> = field[..FUMEN_FIELD_TOP * GRID_WIDTH]
+        .chunks(GRID_WIDTH)
+        .filter(|row| row.contains(&0))
+        .map(|row| row.try_into().unwrap_or([0; GRID_WIDTH]))
+        .collect();
+    let top = FUMEN_FIELD_TOP - rows.len();
+    field[..top * GRID_WIDTH].fill(0);
+    for (y, row) in rows.iter().enumerate() {
+        field[(top + y) * GRID_WIDTH..(top + y + 1) * GRID_WIDTH].copy_from_slice(row);
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if unit < 256 => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            units.push(c as u16);
+            continue;
+        }
+        let rest = chars.as_str();
+        let (digits, skip) = match rest.strip_prefix('u') {
+            Some(hex) => (hex.get(..4), 5),
+            None => (rest.get(..2), 2),
+        };
+        match digits.and_then(|hex| u16::from_str_radix(hex, 16).ok()) {
+            Some(unit) => {
+                units.push(unit);
+                chars = rest[skip..].chars();
+            }
+            None => units.push(c as u16),
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+struct Reader {
+    values: Vec<u32>,
+    index: usize,
+}
+
+impl Reader {
+    fn poll(&mut self, count: usize) -> Result<u32, String> {
+        let values = self
+            .values
+            .get(self.index..self.index + count)
+            .ok_or("fumen ends unexpectedly")?;
+        self.index += count;
+        Ok(values.iter().rev().fold(0, |value, n| value * 64 + n))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.values.len()
+    }
+}
+
+fn push(values: &mut Vec<u32>, mut value: u32, count: usize) {
+    for _ in 0..count {
+        values.push(value % 64);
+        value /= 64;
+    }
+}
+
+#[derive(Clone)]
+pub struct Page {
+    pub field: Vec<Block>,
+    pub piece: Vec<Block>,
+    pub comment: String,
+}
+
+impl Page {
+    pub fn from_tetrus(tetrus: &Tetrus) -> Self {
+        Page {
+            field: tetrus.inactive.clone(),
+            piece: tetrus.active.clone(),
+            comment: String::new(),
+        }
+    }
+
+    pub fn from_lock(tetrus: &Tetrus) -> Self {
+        Page {
+            field: tetrus.get_last_board().to_vec(),
+            piece: tetrus.get_last_piece().to_vec(),
+            comment: String::new(),
+        }
+    }
+
+    pub fn get_block_id(&self) -> Option<BlockType> {
+        self.piece
+            .first()
+            .and_then(|block| BlockType::from_color(block.color))
+    }
+
+    fn to_field(&self) -> Field {
+        let mut field = [0; FUMEN_FIELD_BLOCKS];
+        for block in &self.field {
+            let y = GRID_HEIGHT as i32 - 1 - block.position.y as i32;
+            if let Some(index) = field_index(block.position.x as i32, y) {
+                field[index] = from_color(block.color);
+            }
+        }
+        field
+    }
+
+    // Piece, rotation and center as fumen stores them, if the piece fits on the field.
+    fn to_action(&self) -> Option<(BlockType, u32, usize)> {
+        let id = self.get_block_id()?;
+        let mut cells: Vec<(i32, i32)> = self
+            .piece
+            .iter()
+            .map(|n| {
+                (
+                    n.position.x as i32,
+                    GRID_HEIGHT as i32 - 1 - n.position.y as i32,
+                )
+            })
+            .collect();
+        cells.sort();
+        for rotation in 0..4 {
+            let offsets = shape(&id, rotation);
+            for (dx, dy) in offsets {
+                let (x, y) = (cells[0].0 - dx, cells[0].1 - dy);
+                let mut placed: Vec<(i32, i32)> =
+                    offsets.iter().map(|(ox, oy)| (x + ox, y + oy)).collect();
+                placed.sort();
+                if placed != cells || placed.iter().any(|(x, y)| field_index(*x, *y).is_none()) {
+                    continue;
+                }
+                let (cx, cy) = correction(&id, rotation);
+                return field_index(x - cx, y - cy).map(|index| (id, rotation, index));
+            }
+        }
+        None
+    }
+
+    fn from_fumen(field: &Field, piece: Option<(BlockType, i32, i32, u32)>, comment: &str) -> Self {
+        let mut blocks = Vec::new();
+        for (index, cell) in field.iter().enumerate().take(FUMEN_FIELD_TOP * GRID_WIDTH) {
+            if *cell > 0 {
+                blocks.push(Block {
+                    position: Position::new((index % GRID_WIDTH, index / GRID_WIDTH + 1)),
+                    color: to_color(*cell),
+                    locked_at: 0,
+                });
+            }
+        }
+        let piece = match piece {
+            Some((id, x, y, rotation)) => shape(&id, rotation)
+                .iter()
+                .map(|(dx, dy)| Block {
+                    position: Position::new((
+                        (x + dx) as usize,
+                        (GRID_HEIGHT as i32 - 1 - y - dy) as usize,
+                    )),
+                    color: id.get_color(),
+                    locked_at: 0,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        Page {
+            field: blocks,
+            piece,
+            comment: String::from(comment),
+        }
+    }
+}
+
+// Hold, current piece and next pieces in the `#Q=[H](C)NEXT` form fumen uses for quizzes, followed by
+// any other text of the comment.
+pub fn quiz_comment(hold: Option<&BlockType>, queue: &[BlockType], comment: &str) -> String {
+    let hold: String = hold.map(|id| id.to_char()).into_iter().collect();
+    let current: String = queue.first().map(|id| id.to_char()).into_iter().collect();
+    let next: String = queue.iter().skip(1).map(|id| id.to_char()).collect();
+    let quiz = format!("#Q=[{}]({}){}", hold, current, next);
+    match comment.is_empty() {
+        true => quiz,
+        false => format!("{} {}", quiz, comment),
+    }
+}
+
+// The hold, the queue starting with the current piece, and the rest of a quiz comment.
+pub fn parse_quiz(comment: &str) -> Option<(Option<BlockType>, Vec<BlockType>, String)> {
+    let piece = |text: &str| -> Option<Option<BlockType>> {
+        match text.chars().collect::<Vec<_>>()[..] {
+            [] => Some(None),
+            [c] => BlockType::from_char(c).map(Some),
+            _ => None,
+        }
+    };
+    let rest = comment.strip_prefix("#Q=[")?;
+    let (hold, rest) = rest.split_once(']')?;
+    let (current, rest) = rest.strip_prefix('(')?.split_once(')')?;
+    let (next, text) = rest.split_once([' ', ';']).unwrap_or((rest, ""));
+    let mut queue: Vec<BlockType> = piece(current)?.into_iter().collect();
+    for c in next.chars() {
+        queue.push(BlockType::from_char(c)?);
+    }
+    Some((piece(hold)?, queue, String::from(text.trim())))
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut values = Vec::new();
+    let mut prev = [0; FUMEN_FIELD_BLOCKS];
+    let mut prev_comment = "";
+    let mut repeat: Option<usize> = None;
+
+    for (i, page) in pages.iter().enumerate() {
+        let field = page.to_field();
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for (cell, prev_cell) in field.iter().zip(prev.iter()) {
+            let diff = *cell as u32 + 8 - *prev_cell as u32;
+            match runs.last_mut() {
+                Some((last, count)) if *last == diff => *count += 1,
+                _ => runs.push((diff, 0)),
+            }
+        }
+        let unchanged = runs == [(8, FUMEN_FIELD_BLOCKS as u32 - 1)];
+        match repeat {
+            Some(index) if unchanged && values[index] < 63 => values[index] += 1,
+            _ => {
+                for (diff, count) in &runs {
+                    push(&mut values, diff * FUMEN_FIELD_BLOCKS as u32 + count, 2);
+                }
+                repeat = None;
+                if unchanged {
+                    values.push(0);
+                    repeat = Some(values.len() - 1);
+                }
+            }
+        }
+
+        let action = page.to_action();
+        let comment = page.comment != prev_comment;
+        let mut value = comment as u32;
+        value = value * 2 + (i == 0) as u32;
+        value *= 4;
+        value = value * FUMEN_FIELD_BLOCKS as u32 + action.as_ref().map_or(0, |a| a.2 as u32);
+        value = value * 4 + action.as_ref().map_or(0, |a| ROTATIONS[a.1 as usize]);
+        value = value * 8 + action.as_ref().map_or(0, |a| to_piece(&a.0) as u32);
+        push(&mut values, value, 3);
+
+        if comment {
+            let escaped: Vec<u32> = escape(&page.comment)
+                .chars()
+                .take(FUMEN_COMMENT_MAX)
+                .map(|c| FUMEN_ASCII.find(c).unwrap_or(0) as u32)
+                .collect();
+            push(&mut values, escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                let value = chunk.iter().rev().fold(0, |value, n| value * 96 + n);
+                push(&mut values, value, 5);
+            }
+        }
+
+        prev = field;
+        if let Some((id, rotation, index)) = action {
+            let (cx, cy) = correction(&id, rotation);
+            let x = (index % GRID_WIDTH) as i32 + cx;
+            let y = (FUMEN_FIELD_TOP - 1 - index / GRID_WIDTH) as i32 + cy;
+            for (dx, dy) in shape(&id, rotation) {
+                if let Some(index) = field_index(x + dx, y + dy) {
+                    prev[index] = to_piece(&id);
+                }
+            }
+        }
+        clear_lines(&mut prev);
+        prev_comment = &page.comment;
+    }
+
+    let data: Vec<char> = values
+        .iter()
+        .filter_map(|n| FUMEN_TABLE.chars().nth(*n as usize))
+        .collect();
+    let first = data.len().min(FUMEN_LINE_LENGTH - FUMEN_PREFIX.len());
+    let mut lines = vec![data[..first].iter().collect::<String>()];
+    for chunk in data[first..].chunks(FUMEN_LINE_LENGTH) {
+        lines.push(chunk.iter().collect());
+    }
+    format!("{}{}", FUMEN_PREFIX, lines.join("?"))
+}
+
+pub fn decode(text: &str) -> Result<Vec<Page>, String> {
+    let text = text.trim();
+    let data = match text.find(FUMEN_PREFIX) {
+        Some(index) => &text[index + FUMEN_PREFIX.len()..],
+        None => return Err(String::from("not a v115 fumen")),
+    };
+    let mut reader = Reader {
+        values: data
+            .chars()
+            .filter(|c| *c != '?' && !c.is_whitespace())
+            .map(|c| {
+                FUMEN_TABLE
+                    .find(c)
+                    .map(|n| n as u32)
+                    .ok_or(format!("invalid fumen character: {}", c))
+            })
+            .collect::<Result<_, _>>()?,
+        index: 0,
+    };
+
+    let mut pages = Vec::new();
+    let mut prev = [0; FUMEN_FIELD_BLOCKS];
+    let mut repeat = 0;
+    let mut comment = String::new();
+    while !reader.is_empty() {
+        let mut field = prev;
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            while index < FUMEN_FIELD_BLOCKS {
+                let value = reader.poll(2)?;
+                let diff = value / FUMEN_FIELD_BLOCKS as u32;
+                let count = (value % FUMEN_FIELD_BLOCKS as u32) as usize + 1;
+                if diff == 8 && count == FUMEN_FIELD_BLOCKS {
+                    repeat = reader.poll(1)?;
+                }
+                if index + count > FUMEN_FIELD_BLOCKS || diff > 16 {
+                    return Err(String::from("invalid fumen field"));
+                }
+                for cell in &mut field[index..index + count] {
+                    let value = *cell as u32 + diff;
+                    if !(8..=16).contains(&value) {
+                        return Err(String::from("invalid fumen field"));
+                    }
+                    *cell = (value - 8) as u8;
+                }
+                index += count;
+            }
+        }
+
+        let mut value = reader.poll(3)?;
+        let piece = (value % 8) as u8;
+        value /= 8;
+        let rotation = ROTATIONS[(value % 4) as usize];
+        value /= 4;
+        let index = (value % FUMEN_FIELD_BLOCKS as u32) as usize;
+        value /= FUMEN_FIELD_BLOCKS as u32;
+        let block_up = value % 2 == 1;
+        let mirror = (value / 2) % 2 == 1;
+        let has_comment = (value / 8) % 2 == 1;
+        let lock = (value / 16) % 2 == 0;
+
+        if has_comment {
+            let length = reader.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut value = reader.poll(5)?;
+                for _ in 0..4 {
+                    let c = FUMEN_ASCII
+                        .chars()
+                        .nth((value % 96) as usize)
+                        .ok_or("invalid fumen comment")?;
+                    escaped.push(c);
+                    value /= 96;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        let action = match from_piece(piece) {
+            Some(id) => {
+                let (cx, cy) = correction(&id, rotation);
+                let x = (index % GRID_WIDTH) as i32 + cx;
+                // The index may point into the garbage row below the field
+                let y = FUMEN_FIELD_TOP as i32 - 1 - (index / GRID_WIDTH) as i32 + cy;
+                let cells: Vec<usize> = shape(&id, rotation)
+                    .iter()
+                    .filter_map(|(dx, dy)| field_index(x + dx, y + dy))
+                    .collect();
+                if cells.len() != 4 {
+                    return Err(String::from("fumen piece is outside the field"));
+                }
+                Some((id, x, y, rotation, cells))
+            }
+            None => None,
+        };
+        pages.push(Page::from_fumen(
+            &field,
+            action
+                .as_ref()
+                .map(|(id, x, y, rotation, _)| (id.clone(), *x, *y, *rotation)),
+            &comment,
+        ));
+
+        prev = field;
+        if lock {
+            if let Some((_, _, _, _, cells)) = &action {
+                for index in cells {
+                    prev[*index] = piece;
+                }
+            }
+            clear_lines(&mut prev);
+            if block_up {
+                prev.copy_within(GRID_WIDTH.., 0);
+                prev[FUMEN_FIELD_BLOCKS - GRID_WIDTH..].fill(0);
+            }
+            if mirror {
+                for row in prev.chunks_mut(GRID_WIDTH).take(FUMEN_FIELD_TOP) {
+                    row.reverse();
+                }
+            }
+        }
+    }
+    if pages.is_empty() {
+        return Err(String::from("fumen has no pages"));
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(blocks: &[Block]) -> Vec<(usize, usize)> {
+        let mut cells: Vec<(usize, usize)> = blocks
+            .iter()
+            .map(|n| (n.position.x, n.position.y))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn decode_empty() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].field.is_empty());
+        assert!(pages[0].piece.is_empty());
+        assert_eq!(pages[0].comment, "");
+    }
+
+    #[test]
+    fn decode_field_and_piece() {
+        // Garbage along the bottom with a hole on the right and an I standing in it
+        let pages = decode("v115@bhI8KepIJ").unwrap();
+        assert_eq!(pages.len(), 1);
+        let bottom: Vec<(usize, usize)> = (0..9).map(|x| (x, GRID_HEIGHT - 1)).collect();
+        assert_eq!(cells(&pages[0].field), bottom);
+        assert!(pages[0].field.iter().all(|n| n.color == GREY));
+        assert!(pages[0].get_block_id() == Some(BlockType::I));
+        assert_eq!(cells(&pages[0].piece), [(9, 20), (9, 21), (9, 22), (9, 23)]);
+    }
+
+    #[test]
+    fn decode_locked_piece_on_repeated_field() {
+        // The first field is repeated for the second page, which shows the T locked
+        let pages = decode("v115@vhBVQJAAA").unwrap();
+        assert_eq!(pages.len(), 2);
+        let t = [(3, 23), (4, 22), (4, 23), (5, 23)];
+        assert!(pages[0].field.is_empty());
+        assert!(pages[0].get_block_id() == Some(BlockType::T));
+        assert_eq!(cells(&pages[0].piece), t);
+        assert_eq!(cells(&pages[1].field), t);
+        assert!(pages[1]
+            .field
+            .iter()
+            .all(|n| n.color == BlockType::T.get_color()));
+        assert!(pages[1].piece.is_empty());
+    }
+
+    #[test]
+    fn decode_comment() {
+        let pages = decode("v115@vhAAgWFAIoMDEPBAAA").unwrap();
+        assert_eq!(pages[0].comment, "Hello");
+        assert!(pages[0].field.is_empty());
+        assert!(pages[0].piece.is_empty());
+    }
+
+    #[test]
+    fn decode_skips_line_breaks() {
+        let pages = decode("v115@vhBV?QJAAA").unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(cells(&pages[1].field), [(3, 23), (4, 22), (4, 23), (5, 23)]);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for text in [
+            "v115@vhAAgH",
+            "v115@bhI8KepIJ",
+            "v115@vhBVQJAAA",
+            "v115@vhAAgWFAIoMDEPBAAA",
+        ] {
+            assert_eq!(encode(&decode(text).unwrap()), text);
+        }
+    }
+
+    #[test]
+    fn encode_long_game_with_line_breaks() {
+        let pages: Vec<Page> = (0..20)
+            .map(|i| Page {
+                field: Vec::new(),
+                piece: Vec::new(),
+                comment: format!("page {}", i),
+            })
+            .collect();
+        let text = encode(&pages);
+        let lines: Vec<&str> = text.split('?').collect();
+        assert!(lines.len() > 2);
+        assert_eq!(lines[0].len(), FUMEN_LINE_LENGTH);
+        assert_eq!(lines[1].len(), FUMEN_LINE_LENGTH);
+        let decoded = decode(&text).unwrap();
+        assert_eq!(decoded.len(), 20);
+        assert_eq!(decoded[19].comment, "page 19");
+        assert_eq!(encode(&decoded), text);
+    }
+
+    #[test]
+    fn quiz_round_trip() {
+        let queue = [BlockType::T, BlockType::I, BlockType::O];
+        let comment = quiz_comment(Some(&BlockType::S), &queue, "T-spin setup");
+        assert_eq!(comment, "#Q=[S](T)IO T-spin setup");
+        let (hold, parsed, text) = parse_quiz(&comment).unwrap();
+        assert!(hold == Some(BlockType::S));
+        assert!(parsed == queue);
+        assert_eq!(text, "T-spin setup");
+
+        let (hold, parsed, text) = parse_quiz("#Q=[](L)").unwrap();
+        assert!(hold.is_none() && parsed == [BlockType::L] && text.is_empty());
+        assert!(parse_quiz("Hello").is_none());
+        assert!(parse_quiz("#Q=[X](T)").is_none());
+    }
+
+    #[test]
+    fn decode_rejects_malformed() {
+        for text in [
+            "",
+            "vhAAgH",
+            "v115@",
+            "v115@vh",
+            "v115@vhAAg",
+            "v115@vhA!gH",
+            "v115@//AAgH",
+            "v115@vhARgH",
+            "v115@vhAAgWFAIoM",
+        ] {
+            assert!(decode(text).is_err(), "{}", text);
+        }
+        // Any truncation or corruption returns rather than panicking
+        let text = "v115@bhI8KepIJvhAAgWFAIoMDEPBAAA";
+        for end in FUMEN_PREFIX.len()..text.len() {
+            let _ = decode(&text[..end]);
+            for c in FUMEN_TABLE.chars() {
+                let mut corrupt = String::from(&text[..end]);
+                corrupt.push(c);
+                corrupt.push_str(&text[end + 1..]);
+                let _ = decode(&corrupt);
+            }
+        }
+    }
+}
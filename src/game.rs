@@ -1,181 +1,1264 @@
+use crate::ai::*;
 use crate::constants::*;
+use crate::daily::*;
+use crate::editor::*;
+use crate::effects::*;
+use crate::fumen::*;
+use crate::json::*;
+use crate::layout::*;
+use crate::opener::*;
+use crate::play::*;
+use crate::puzzle::*;
+use crate::save::*;
+use crate::settings::*;
 use crate::sounds::*;
+use crate::stats::*;
+use crate::survival::*;
+use crate::tbp::*;
 use crate::tetrus::*;
+use crate::theme::*;
 use macroquad::prelude::*;
+use macroquad::rand::rand;
 
 pub enum State {
     Welcome,
+    LevelSelect,
+    DailyHistory,
+    Stats,
+    Settings,
+    Editor,
     Running,
     GameOver,
 }
 
 pub struct Game {
-    tetrus: Tetrus,
-    time: f64,
+    play: Play,
+    sounds: SoundCollection,
+    bot_command: Option<String>,
     state: State,
+    survival_settings: SurvivalSettings,
+    difficulty: Difficulty,
+    puzzles: Vec<Puzzle>,
+    selected: usize,
+    solved: Vec<String>,
+    openers: Vec<Opener>,
+    learned: Vec<String>,
+    editor: Editor,
+    daily_history: Vec<DailyRecord>,
+    saved: Option<Json>,
+    stats: Vec<DayStats>,
+    hud: bool,
+    settings: Settings,
+    themes: Vec<Theme>,
+    theme: usize,
+    // The selected theme with the palette and glyph settings applied
+    style: Theme,
+    setting: usize,
+    effects: Effects,
+    rival_effects: Effects,
 }
 
 impl Game {
     pub async fn new() -> Self {
-        let mut bg_music = SoundCollection::new();
+        let mut sounds = SoundCollection::new();
         #[cfg(target_arch = "wasm32")]
-        bg_music
-            .add_sound("/audio/tetrus_background.wav", "bg_track")
-            .await;
+        {
+            sounds
+                .add_sound("/audio/tetrus_background.wav", "bg_track")
+                .await;
+            sounds.add_sound("/audio/tetrus_drop.wav", "drop").await;
+            sounds.add_sound("/audio/tetrus_rotate.wav", "rotate").await;
+            sounds.add_sound("/audio/tetrus_set.wav", "set").await;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            sounds
+                .add_sound("audio/tetrus_background.wav", "bg_track")
+                .await;
+            sounds.add_sound("audio/tetrus_drop.wav", "drop").await;
+            sounds.add_sound("audio/tetrus_rotate.wav", "rotate").await;
+            sounds.add_sound("audio/tetrus_set.wav", "set").await;
+        }
+        sounds.play("bg_track", BACKGROUND_SOUND_PARAMS);
+        let settings = Settings::load();
+        let themes = Theme::load_all().await;
+        let theme = themes
+            .iter()
+            .position(|t| t.name == settings.theme)
+            .unwrap_or(0);
+        let style = themes[theme].with_access(settings.palette, settings.glyphs);
+        let effects = Effects::new(settings.effects);
+        let rival_effects = Effects::new(settings.effects);
         #[cfg(not(target_arch = "wasm32"))]
-        bg_music
-            .add_sound("audio/tetrus_background.wav", "bg_track")
-            .await;
-        bg_music.play("bg_track", BACKGROUND_SOUND_PARAMS);
+        prevent_quit();
+        let saved = load_lines(GAME_SAVE);
+        let saved = match Json::parse(&saved.join("\n")) {
+            Ok(json) => Some(json),
+            Err(e) if !saved.is_empty() => {
+                eprintln!("{}: {}", GAME_SAVE, e);
+                None
+            }
+            Err(_) => None,
+        };
         Game {
-            tetrus: Tetrus::new().await,
-            time: 0.0,
+            play: Play::new(),
+            sounds,
+            bot_command: std::env::args().skip_while(|a| a != "--bot").nth(1),
             state: State::Welcome,
+            survival_settings: SurvivalSettings::default(),
+            difficulty: Difficulty::Medium,
+            puzzles: Vec::new(),
+            selected: 0,
+            solved: load_lines(PUZZLE_SAVE),
+            openers: Vec::new(),
+            learned: load_lines(OPENER_SAVE),
+            editor: Editor::new(),
+            daily_history: load_history(),
+            saved,
+            stats: load_stats(),
+            hud: false,
+            settings,
+            themes,
+            theme,
+            style,
+            setting: 0,
+            effects,
+            rival_effects,
+        }
+    }
+
+    async fn reset(&mut self) {
+        self.finish_stats();
+        // A new game replaces the one that could be continued
+        self.saved = None;
+        let bot = (self.play.mode == Mode::Cpu).then(|| self.create_bot());
+        self.play.reset(&self.survival_settings, bot);
+        self.effects.clear();
+        self.rival_effects.clear();
+        match self.play.mode {
+            Mode::Puzzle => self.play.set_puzzle(&self.puzzles[self.selected]),
+            Mode::Opener => self.play.set_opener(&self.openers[self.selected]),
+            Mode::Editor => self.play.set_position(
+                self.editor.get_board(),
+                self.editor.get_queue(),
+                self.editor.get_hold(),
+            ),
+            Mode::Daily => self.play.daily = start_daily(&mut self.daily_history),
+            _ => (),
+        }
+    }
+
+    fn create_bot(&self) -> Box<dyn Controller> {
+        let bot = Bot::new(self.difficulty, rand() as u64);
+        if let Some(command) = &self.bot_command {
+            match TbpBot::new(command) {
+                Ok(tbp) => return Box::new(tbp.with_fallback(bot)),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        Box::new(bot)
+    }
+
+    fn finish_daily(&mut self) {
+        if let Some(day) = self.play.daily.take() {
+            finish_daily(&mut self.daily_history, day, self.play.get_score());
+        }
+    }
+
+    fn finish_stats(&mut self) {
+        if self.play.session.pieces > 0 {
+            let session = std::mem::take(&mut self.play.session);
+            record_game(&mut self.stats, session, self.play.time);
         }
     }
 
+    fn resume(&mut self, json: &Json) -> Result<(), String> {
+        self.play.resume(json)?;
+        self.effects.clear();
+        Ok(())
+    }
+
+    fn quit(&mut self) {
+        if matches!(self.state, State::Running) {
+            if self.play.is_saveable() && !self.play.tetrus.is_game_over() {
+                save_lines(GAME_SAVE, &[self.play.to_json().to_string()]);
+            } else {
+                self.finish_stats();
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        std::process::exit(0);
+    }
+
+    fn undo(&mut self) -> bool {
+        self.effects.clear();
+        self.play.undo()
+    }
+
+    fn redo(&mut self) {
+        if self.play.redo() {
+            self.effects.clear();
+        }
+    }
+
+    fn update_frame(&mut self) {
+        let (events, rival_events) = self.play.update_frame(is_key_down(KeyCode::S));
+        self.effects.update(&self.play.tetrus, &events);
+        if let Some(rival) = &self.play.rival {
+            self.rival_effects.update(rival, &rival_events);
+        }
+        for event in events.iter().chain(&rival_events) {
+            if let Event::Clear(clear) = event {
+                for _ in 0..clear.lines {
+                    self.sounds.play("set", SOUND_PARAMS);
+                }
+            }
+        }
+    }
+
+    fn get_layout(&self) -> Layout {
+        let mut layout = match self.play.rival {
+            Some(_) => Layout::versus(),
+            None => Layout::new(),
+        };
+        layout.typeface = self.get_theme().font;
+        layout
+    }
+
+    fn get_theme(&self) -> &Theme {
+        &self.style
+    }
+
     fn draw_board(&mut self) {
-        let block_size_width = (screen_width() - (DISPLAY_PADDING * 2.0)) / 10.0;
-        let block_size_height = (screen_height() - (DISPLAY_PADDING * 2.0)) / 20.0;
-        for block in &self.tetrus.active {
-            if block.position.y > 3 {
-                draw_rectangle(
-                    (block.position.x as f32 * block_size_width) + DISPLAY_PADDING,
-                    (block.position.y as f32 * block_size_height) + DISPLAY_PADDING - (4.0 * block_size_height),
-                    block_size_width,
-                    block_size_height,
-                    block.color,
-                )
-            }
-        }
-        for block in &self.tetrus.inactive {
-            if block.position.y > 3 {
-                draw_rectangle(
-                    (block.position.x as f32 * block_size_width) + DISPLAY_PADDING,
-                    (block.position.y as f32 * block_size_height) + DISPLAY_PADDING - (4.0 * block_size_height),
-                    block_size_width,
-                    block_size_height,
-                    block.color,
-                )
-            }
-        }
-        for i in 0..11 {
-            draw_line(
-                (i as f32 * block_size_width) + DISPLAY_PADDING,
-                DISPLAY_PADDING,
-                (i as f32 * block_size_width) + DISPLAY_PADDING,
-                screen_height() - DISPLAY_PADDING,
-                1.0,
-                WHITE,
-            );
-        }
-        for i in 0..21 {
-            draw_line(
-                DISPLAY_PADDING,
-                (i as f32 * block_size_height) + DISPLAY_PADDING,
-                screen_width() - DISPLAY_PADDING,
-                (i as f32 * block_size_height) + DISPLAY_PADDING,
-                1.0,
-                WHITE,
+        let layout = Layout::new();
+        self.draw_well(&self.play.tetrus, layout.well, &self.effects);
+        self.draw_hold(&self.play.tetrus, layout.left, layout.cell);
+    }
+
+    // The held piece at the top of a side panel, dimmed once it has been used for this piece.
+    fn draw_hold(&self, tetrus: &Tetrus, panel: Rect, cell: f32) {
+        let id = match tetrus.get_hold() {
+            Some(id) => id,
+            None => return,
+        };
+        let theme = self.get_theme();
+        let alpha = if tetrus.is_hold_used() { 0.5 } else { 1.0 };
+        let size = cell / 2.0;
+        let x = panel.x + (panel.w - size * 2.0) / 2.0;
+        for (bx, by) in id.get_blocks() {
+            let rect = Rect::new(
+                x + (bx as f32 - 4.0) * size,
+                panel.y + by as f32 * size,
+                size,
+                size,
+            );
+            theme.draw_block(rect, id.get_color(), alpha);
+        }
+    }
+
+    fn draw_well(&self, tetrus: &Tetrus, well: Rect, effects: &Effects) {
+        let theme = self.get_theme();
+        for block in &tetrus.active {
+            if block.position.y >= HIDDEN_ROWS {
+                let rect = cell_rect(well, block.position.x, block.position.y);
+                theme.draw_block(rect, block.color, 1.0);
+            }
+        }
+        for block in &tetrus.inactive {
+            let offset = effects.row_offset(block.position.y);
+            if block.position.y as f32 + offset >= HIDDEN_ROWS as f32 {
+                let rect = cell_rect(well, block.position.x, block.position.y);
+                let rect = Rect {
+                    y: rect.y + offset * rect.h,
+                    ..rect
+                };
+                theme.draw_block(rect, block.color, self.play.get_alpha(tetrus, block));
+            }
+        }
+        theme.draw_grid(well, well.w / GRID_WIDTH as f32);
+        effects.draw(well, theme);
+    }
+
+    fn draw_versus(&mut self) {
+        let rival = match &self.play.rival {
+            Some(rival) => rival,
+            None => return,
+        };
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let wells = [layout.well, layout.rival.unwrap_or(layout.well)];
+        let meter = layout.cell / 3.0;
+        let meters = [wells[0].right() + meter, wells[1].x - meter * 2.0];
+        if self.hud {
+            self.draw_hud(&layout);
+        }
+        let effects = [&self.effects, &self.rival_effects];
+        for (i, tetrus) in [&self.play.tetrus, rival].into_iter().enumerate() {
+            let well = wells[i];
+            self.draw_well(tetrus, well, effects[i]);
+            self.draw_hold(tetrus, [layout.left, layout.right][i], layout.cell);
+            let pending = (self.play.versus.get_pending(i) as f32 * layout.cell).min(well.h);
+            draw_rectangle(
+                meters[i],
+                well.bottom() - pending,
+                meter,
+                pending,
+                theme.warning,
+            );
+            let name = match (i, &self.play.bot) {
+                (1, Some(_)) => String::from("CPU"),
+                _ => format!("P{}", i + 1),
+            };
+            layout.draw_text(
+                format!("{}  sent {}", name, self.play.versus.get_sent(i)).as_ref(),
+                well.x,
+                well.y - layout.cell / 3.0,
+                30.0,
+                theme.text,
+            );
+        }
+    }
+
+    // Live numbers for the current game, stacked at the bottom of the panel left of the well.
+    fn draw_hud(&self, layout: &Layout) {
+        let theme = self.get_theme();
+        let items = self.play.get_hud();
+        let panel = layout.left;
+        let right = panel.right() - layout.cell / 3.0;
+        let spacing = (layout.font(18.0) + layout.font(26.0) + layout.font(12.0))
+            .min(panel.h / items.len() as f32);
+        let top = panel.bottom() - spacing * items.len() as f32;
+        for (i, (label, value)) in items.iter().enumerate() {
+            let y = top + spacing * i as f32;
+            layout.draw_right(label, right, y + layout.font(18.0), 18.0, theme.muted);
+            layout.draw_right(
+                value,
+                right,
+                y + layout.font(18.0) + layout.font(26.0),
+                26.0,
+                theme.text,
             );
         }
     }
 
     fn draw_score(&mut self) {
-        draw_text(
-            format!("{:05}", self.tetrus.get_score()).as_ref(),
-            5.0,
-            40.0,
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        layout.draw_text(
+            format!("{:05}", self.play.get_score()).as_ref(),
+            layout.top.x,
+            layout.top.bottom() - layout.cell / 3.0,
             50.0,
-            WHITE,
+            theme.text,
         );
     }
 
+    fn draw_garbage_timer(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        draw_rectangle(
+            layout.well.x,
+            layout.well.bottom() + layout.cell / 3.0,
+            layout.well.w * self.play.survival.get_progress() as f32,
+            layout.cell / 3.0,
+            theme.muted,
+        );
+    }
+
+    fn draw_grade(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let y = layout.status_y();
+        let level_text = format!(
+            "LV {:03}/{}",
+            self.play.master.get_level(),
+            self.play.master.get_section()
+        );
+        layout.draw_text(&level_text, layout.bottom.x, y, 40.0, theme.text);
+        let grade_text = format!("Grade {}", self.play.master.get_grade());
+        layout.draw_right(&grade_text, layout.bottom.right(), y, 40.0, theme.text);
+    }
+
+    fn draw_queue(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let size = layout.cell / 2.0;
+        let x = layout.right.x + (layout.right.w - size * 2.0) / 2.0;
+        for (i, id) in self
+            .play
+            .tetrus
+            .get_queue()
+            .iter()
+            .take(PREVIEW_COUNT)
+            .enumerate()
+        {
+            for (bx, by) in id.get_blocks() {
+                let rect = Rect::new(
+                    x + (bx as f32 - 4.0) * size,
+                    layout.well.y + (by as f32 + i as f32 * 5.0) * size,
+                    size,
+                    size,
+                );
+                theme.draw_block(rect, id.get_color(), 1.0);
+            }
+        }
+    }
+
+    fn draw_puzzle(&mut self) {
+        if let Some(run) = &self.play.puzzle_run {
+            let layout = self.get_layout();
+            let theme = self.get_theme();
+            let y = layout.status_y();
+            let goal_text = self.puzzles[self.selected].goal.describe();
+            layout.draw_text(&goal_text, layout.bottom.x, y, 30.0, theme.text);
+            let pieces_text = format!("Pieces {}", run.get_remaining());
+            layout.draw_right(&pieces_text, layout.bottom.right(), y, 30.0, theme.text);
+        }
+        self.draw_queue();
+    }
+
+    fn draw_finesse(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let y = layout.status_y();
+        let faults_text = format!(
+            "Faults {}/{}",
+            self.play.finesse.get_faults(),
+            self.play.finesse.get_pieces()
+        );
+        layout.draw_text(&faults_text, layout.bottom.x, y, 30.0, theme.text);
+        if let Some(last) = self.play.finesse.get_last() {
+            let last_text = format!("{} {}/{}", last.id.to_char(), last.presses, last.minimum);
+            layout.draw_right(
+                &last_text,
+                layout.bottom.right(),
+                y,
+                30.0,
+                if last.is_fault() {
+                    theme.warning
+                } else {
+                    theme.text
+                },
+            );
+        }
+        for (i, id) in "IJLOSTZ".chars().enumerate() {
+            layout.draw_text(
+                format!("{} {}", id, self.play.finesse.get_by_piece()[i]).as_ref(),
+                layout.left.x,
+                layout.left.y + (i + 1) as f32 * layout.font(30.0),
+                30.0,
+                theme.muted,
+            );
+        }
+        for (x, faults) in self.play.finesse.get_by_column().iter().enumerate() {
+            let cell = cell_rect(layout.well, x, HIDDEN_ROWS);
+            layout.draw_text(
+                faults.to_string().as_ref(),
+                cell.x + cell.w * 0.35,
+                layout.well.bottom() + layout.font(20.0),
+                20.0,
+                theme.muted,
+            );
+        }
+    }
+
+    fn draw_opener(&mut self) {
+        let run = match &self.play.opener_run {
+            Some(run) => run,
+            None => return,
+        };
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        for block in run.get_remaining(&self.play.tetrus.inactive) {
+            let rect = cell_rect(layout.well, block.position.x, block.position.y);
+            theme.draw_block(rect, block.color, TARGET_ALPHA);
+        }
+        let y = layout.status_y();
+        layout.draw_text(
+            &self.openers[self.selected].name,
+            layout.bottom.x,
+            y,
+            30.0,
+            theme.text,
+        );
+        let (stage, stages) = run.get_stage();
+        let status_text = match (run.is_complete(), stages) {
+            (true, _) => String::from("Complete"),
+            (false, 1) => format!("Retries {}", self.play.retries),
+            (false, _) => format!("Stage {}/{}  Retries {}", stage, stages, self.play.retries),
+        };
+        layout.draw_right(&status_text, layout.bottom.right(), y, 30.0, theme.text);
+        if run.is_mismatch() {
+            layout.draw_centered(
+                MISMATCH_TEXT,
+                layout.well.x + layout.well.w / 2.0,
+                layout.well.y + layout.well.h / 2.0,
+                30.0,
+                theme.warning,
+            );
+        }
+        self.draw_queue();
+    }
+
     fn draw_time(&mut self) {
-        draw_text(
-            format!("{:04}", self.time as u64).as_ref(),
-            screen_width() - 100.0,
-            40.0,
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        layout.draw_right(
+            format!("{:04}", self.play.time as u64).as_ref(),
+            layout.top.right(),
+            layout.top.bottom() - layout.cell / 3.0,
             50.0,
-            WHITE,
+            theme.text,
         );
     }
 
     fn player_input(&mut self) {
         if is_key_pressed(KeyCode::Escape) {
-            #[cfg(not(target_arch = "wasm32"))]
-            std::process::exit(0);
+            self.quit();
         }
-        if self.tetrus.is_active() {
+        if is_key_pressed(KeyCode::C) {
+            set_clipboard(&encode(&[Page::from_tetrus(&self.play.tetrus)]));
+        }
+        if is_key_pressed(KeyCode::Tab) {
+            self.hud = !self.hud;
+        }
+        if self.play.is_practice() {
+            if is_key_pressed(KeyCode::U) {
+                self.undo();
+            } else if is_key_pressed(KeyCode::R) {
+                self.redo();
+            }
+        }
+        if self.is_opener_mismatch() {
+            return;
+        }
+        if self.play.tetrus.is_active() {
+            if [
+                KeyCode::A,
+                KeyCode::D,
+                KeyCode::S,
+                KeyCode::W,
+                KeyCode::Space,
+                KeyCode::Q,
+            ]
+            .into_iter()
+            .any(is_key_pressed)
+            {
+                self.play.finesse.on_press();
+                self.play.session.on_key();
+            }
+            if is_key_pressed(KeyCode::Space) || is_key_pressed(KeyCode::S) {
+                self.play.finesse.on_drop();
+            }
             if is_key_pressed(KeyCode::A) {
-                self.tetrus.player_move(Movement::Left);
+                self.play.tetrus.player_move(Movement::Left);
             } else if is_key_pressed(KeyCode::D) {
-                self.tetrus.player_move(Movement::Right);
+                self.play.tetrus.player_move(Movement::Right);
             } else if is_key_pressed(KeyCode::Space) {
-                self.tetrus.player_move(Movement::Drop);
-                self.tetrus.sounds.play("drop", SOUND_PARAMS);
+                let before = self.play.tetrus.active.clone();
+                self.play.tetrus.player_move(Movement::Drop);
+                let frame = self.play.tetrus.get_frame();
+                self.effects
+                    .on_drop(&before, &self.play.tetrus.active, frame);
+                self.sounds.play("drop", SOUND_PARAMS);
             } else if is_key_pressed(KeyCode::W) {
-                self.tetrus.player_move(Movement::Rotate);
-                self.tetrus.sounds.play("rotate", SOUND_PARAMS);
+                self.play.tetrus.player_move(Movement::Rotate);
+                self.sounds.play("rotate", SOUND_PARAMS);
+            } else if is_key_pressed(KeyCode::Q) {
+                self.play.tetrus.hold_piece();
             }
         }
-        if is_key_pressed(KeyCode::S) {
-            self.tetrus.swap_tick();
-        }
-        if is_key_released(KeyCode::S) {
-            self.tetrus.swap_tick();
+        self.play.tetrus.set_soft_drop(is_key_down(KeyCode::S));
+        if let (Some(rival), None) = (&mut self.play.rival, &self.play.bot) {
+            if rival.is_active() {
+                if is_key_pressed(KeyCode::Left) {
+                    rival.player_move(Movement::Left);
+                } else if is_key_pressed(KeyCode::Right) {
+                    rival.player_move(Movement::Right);
+                } else if is_key_pressed(KeyCode::Enter) {
+                    let before = rival.active.clone();
+                    rival.player_move(Movement::Drop);
+                    let frame = rival.get_frame();
+                    self.rival_effects.on_drop(&before, &rival.active, frame);
+                    self.sounds.play("drop", SOUND_PARAMS);
+                } else if is_key_pressed(KeyCode::Up) {
+                    rival.player_move(Movement::Rotate);
+                    self.sounds.play("rotate", SOUND_PARAMS);
+                } else if is_key_pressed(KeyCode::Backspace) {
+                    rival.hold_piece();
+                }
+            }
+            rival.set_soft_drop(is_key_down(KeyCode::Down));
         }
     }
 
     async fn welcome(&mut self) {
-        let tetrus_size = measure_text(TETRUS_TEXT, Some(Font::default()), 100, 1.0);
-        let space_size = measure_text(SPACE_TEXT, Some(Font::default()), 40, 1.0);
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let tetrus_size = layout.measure(TETRUS_TEXT, 100.0);
+        let space_size = layout.measure(SPACE_TEXT, 40.0);
+        let mode_size = layout.measure(MODE_TEXT_PLACEHOLDER, 40.0);
 
-        draw_text(
+        layout.draw_text(
             TETRUS_TEXT,
             screen_width() / 2.0 - tetrus_size.width / 2.0,
             screen_height() / 2.0 - tetrus_size.height / 2.0,
             100.0,
-            WHITE,
+            theme.text,
         );
-        draw_text(
+        layout.draw_text(
             SPACE_TEXT,
             screen_width() / 2.0 - space_size.width / 2.0,
             screen_height() / 2.0 - space_size.height / 2.0 + tetrus_size.height / 2.0,
             40.0,
-            WHITE,
+            theme.text,
+        );
+        layout.draw_text(
+            format!("< {} >", self.play.mode.name()).as_ref(),
+            screen_width() / 2.0 - mode_size.width / 2.0,
+            screen_height() / 2.0 - mode_size.height / 2.0 + tetrus_size.height,
+            40.0,
+            theme.text,
         );
+        if self.play.mode == Mode::Survival {
+            let holes_text = format!("Holes: {}", self.survival_settings.holes.name());
+            let holes_size = layout.measure(&holes_text, 20.0);
+            layout.draw_text(
+                &holes_text,
+                screen_width() / 2.0 - holes_size.width / 2.0,
+                screen_height() / 2.0 - holes_size.height / 2.0 + tetrus_size.height * 1.5,
+                20.0,
+                theme.muted,
+            );
+        }
 
-        if is_key_pressed(KeyCode::Space) {
+        if self.play.mode == Mode::Cpu {
+            let difficulty_text = match &self.bot_command {
+                Some(command) => format!("Bot: {}", command),
+                None => format!("Difficulty: {}", self.difficulty.name()),
+            };
+            let difficulty_size = layout.measure(&difficulty_text, 20.0);
+            layout.draw_text(
+                &difficulty_text,
+                screen_width() / 2.0 - difficulty_size.width / 2.0,
+                screen_height() / 2.0 - difficulty_size.height / 2.0 + tetrus_size.height * 1.5,
+                20.0,
+                theme.muted,
+            );
+        }
+
+        if self.play.mode == Mode::Daily {
+            let day = today();
+            let daily_text = match self.daily_history.iter().find(|r| r.day == day) {
+                Some(record) => format!("{}  played: {:05}", date_string(day), record.score),
+                None => format!("{}  not played yet", date_string(day)),
+            };
+            let daily_size = layout.measure(&daily_text, 20.0);
+            layout.draw_text(
+                &daily_text,
+                screen_width() / 2.0 - daily_size.width / 2.0,
+                screen_height() / 2.0 - daily_size.height / 2.0 + tetrus_size.height * 1.5,
+                20.0,
+                theme.muted,
+            );
+        }
+
+        let stack_text = format!("Stack: {}", self.play.visibility.name());
+        let stack_size = layout.measure(&stack_text, 20.0);
+        layout.draw_text(
+            &stack_text,
+            screen_width() / 2.0 - stack_size.width / 2.0,
+            screen_height() / 2.0 - stack_size.height / 2.0 + tetrus_size.height * 2.0,
+            20.0,
+            theme.muted,
+        );
+
+        if let Some(saved) = &self.saved {
+            let continue_text =
+                format!("C: Continue {}", saved.get_str("mode").unwrap_or_default());
+            let continue_size = layout.measure(&continue_text, 20.0);
+            layout.draw_text(
+                &continue_text,
+                screen_width() / 2.0 - continue_size.width / 2.0,
+                screen_height() / 2.0 - continue_size.height / 2.0 + tetrus_size.height * 2.5,
+                20.0,
+                theme.text,
+            );
+        }
+
+        if is_key_pressed(KeyCode::C) && self.saved.is_some() {
+            if let Some(saved) = self.saved.take() {
+                match self.resume(&saved) {
+                    Ok(()) => {
+                        remove_save(GAME_SAVE);
+                        self.state = State::Running;
+                    }
+                    Err(e) => eprintln!("{}: {}", GAME_SAVE, e),
+                }
+            }
+        } else if is_key_pressed(KeyCode::A) {
+            self.play.mode = self.play.mode.prev();
+        } else if is_key_pressed(KeyCode::D) {
+            self.play.mode = self.play.mode.next();
+        } else if is_key_pressed(KeyCode::H) && self.play.mode == Mode::Survival {
+            self.survival_settings.holes = self.survival_settings.holes.next();
+        } else if is_key_pressed(KeyCode::H) && self.play.mode == Mode::Daily {
+            self.state = State::DailyHistory;
+        } else if is_key_pressed(KeyCode::H) && self.play.mode == Mode::Cpu {
+            self.difficulty = self.difficulty.next();
+        } else if is_key_pressed(KeyCode::V) {
+            self.play.visibility = self.play.visibility.next();
+        } else if is_key_pressed(KeyCode::S) {
+            self.state = State::Stats;
+        } else if is_key_pressed(KeyCode::O) {
+            self.state = State::Settings;
+        } else if is_key_pressed(KeyCode::Space) && self.play.mode == Mode::Puzzle {
+            if self.puzzles.is_empty() {
+                self.puzzles = Puzzle::load_all().await;
+            }
+            self.selected = self.selected.min(self.puzzles.len().saturating_sub(1));
+            self.state = State::LevelSelect;
+        } else if is_key_pressed(KeyCode::Space) && self.play.mode == Mode::Opener {
+            if self.openers.is_empty() {
+                self.openers = Opener::load_all().await;
+            }
+            self.selected = self.selected.min(self.openers.len().saturating_sub(1));
+            self.state = State::LevelSelect;
+        } else if is_key_pressed(KeyCode::Space) && self.play.mode == Mode::Editor {
+            self.state = State::Editor;
+        } else if is_key_pressed(KeyCode::Space) {
+            self.reset().await;
             self.state = State::Running;
         } else if is_key_pressed(KeyCode::Escape) {
-            #[cfg(not(target_arch = "wasm32"))]
-            std::process::exit(0);
+            self.quit();
+        }
+        next_frame().await;
+    }
+
+    async fn level_select(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let padding = layout.scaled(DISPLAY_PADDING);
+        let (title, empty, levels): (&str, &str, Vec<(bool, &str)>) = match self.play.mode {
+            Mode::Opener => (
+                OPENERS_TEXT,
+                NO_OPENERS_TEXT,
+                self.openers
+                    .iter()
+                    .map(|o| (self.learned.contains(&o.file), o.name.as_str()))
+                    .collect(),
+            ),
+            _ => (
+                PUZZLES_TEXT,
+                NO_PUZZLES_TEXT,
+                self.puzzles
+                    .iter()
+                    .map(|p| (self.solved.contains(&p.file), p.name.as_str()))
+                    .collect(),
+            ),
+        };
+        let count = levels.len();
+        let title_size = layout.measure(title, 60.0);
+        layout.draw_text(
+            title,
+            screen_width() / 2.0 - title_size.width / 2.0,
+            padding,
+            60.0,
+            theme.text,
+        );
+        if levels.is_empty() {
+            layout.draw_text(empty, padding, padding * 2.0, 30.0, theme.muted);
+        }
+        for (i, (done, name)) in levels.into_iter().enumerate() {
+            let mark = if done { "[x]" } else { "[ ]" };
+            layout.draw_text(
+                format!("{} {}", mark, name).as_ref(),
+                padding,
+                padding * 2.0 + i as f32 * layout.scaled(40.0),
+                30.0,
+                if i == self.selected {
+                    theme.accent
+                } else {
+                    theme.text
+                },
+            );
+        }
+
+        if is_key_pressed(KeyCode::W) {
+            self.selected = self.selected.saturating_sub(1);
+        } else if is_key_pressed(KeyCode::S) {
+            self.selected = (self.selected + 1).min(count.saturating_sub(1));
+        } else if is_key_pressed(KeyCode::Space) && count > 0 {
+            self.play.retries = 0;
+            self.reset().await;
+            self.state = State::Running;
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.state = State::Welcome;
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.quit();
+        }
+        next_frame().await;
+    }
+
+    async fn daily_history(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let padding = layout.scaled(DISPLAY_PADDING);
+        let title_size = layout.measure(DAILY_TEXT, 60.0);
+        layout.draw_text(
+            DAILY_TEXT,
+            screen_width() / 2.0 - title_size.width / 2.0,
+            padding,
+            60.0,
+            theme.text,
+        );
+        let best = self
+            .daily_history
+            .iter()
+            .map(|r| r.score)
+            .max()
+            .unwrap_or(0);
+        for (i, record) in self.daily_history.iter().rev().take(20).enumerate() {
+            layout.draw_text(
+                format!("{}  {:05}", date_string(record.day), record.score).as_ref(),
+                padding,
+                padding * 2.0 + i as f32 * layout.scaled(35.0),
+                30.0,
+                if record.score == best {
+                    theme.accent
+                } else {
+                    theme.text
+                },
+            );
+        }
+
+        if is_key_pressed(KeyCode::Enter) {
+            self.state = State::Welcome;
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.quit();
         }
         next_frame().await;
     }
 
+    async fn lifetime_stats(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let padding = layout.scaled(DISPLAY_PADDING);
+        let title_size = layout.measure(STATS_TEXT, 60.0);
+        layout.draw_text(
+            STATS_TEXT,
+            screen_width() / 2.0 - title_size.width / 2.0,
+            padding,
+            60.0,
+            theme.text,
+        );
+        let total = lifetime(&self.stats);
+        let time = total.time as u64;
+        let lines = [
+            format!("Games: {}", total.games),
+            format!(
+                "Time played: {}:{:02}:{:02}",
+                time / 3600,
+                time / 60 % 60,
+                time % 60
+            ),
+            format!("Pieces: {}", total.pieces),
+            format!("Lines: {}", total.lines),
+            format!(
+                "Clears: {} / {} / {} / {}",
+                total.clears[0], total.clears[1], total.clears[2], total.clears[3]
+            ),
+            format!("T-spins: {}", total.tspins),
+            format!("Perfect clears: {}", total.perfects),
+            format!("Best combo: {}", total.best_combo),
+            format!("Pieces per second: {:.2}", total.pps()),
+            format!("Attack per minute: {:.2}", total.apm()),
+            format!("Keys per piece: {:.2}", total.kpp()),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            layout.draw_text(
+                line,
+                padding / 2.0,
+                padding * 1.6 + i as f32 * layout.scaled(30.0),
+                26.0,
+                theme.text,
+            );
+        }
+
+        let last = today();
+        let days: Vec<Stats> = (last + 1 - STATS_DAYS..=last)
+            .map(|day| match self.stats.iter().find(|r| r.day == day) {
+                Some(record) => record.stats.clone(),
+                None => Stats::new(),
+            })
+            .collect();
+        let top = padding * 1.6 + lines.len() as f32 * layout.scaled(30.0);
+        let height = (screen_height() - top - padding / 2.0) / 2.0;
+        let charts = [
+            (
+                "Lines per day",
+                days.iter().map(|n| n.lines as f64).collect::<Vec<_>>(),
+            ),
+            (
+                "Pieces per second",
+                days.iter().map(|n| n.pps()).collect::<Vec<_>>(),
+            ),
+        ];
+        for (i, (title, values)) in charts.iter().enumerate() {
+            draw_chart(
+                &layout,
+                theme,
+                title,
+                values,
+                last + 1 - STATS_DAYS,
+                Rect::new(
+                    padding / 2.0,
+                    top + height * i as f32,
+                    screen_width() - padding,
+                    height,
+                ),
+            );
+        }
+
+        if is_key_pressed(KeyCode::Enter) {
+            self.state = State::Welcome;
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.quit();
+        }
+        next_frame().await;
+    }
+
+    fn get_settings(&self) -> Vec<(&str, String)> {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        let mut settings = vec![
+            ("Theme", self.get_theme().name.clone()),
+            ("Palette", String::from(self.settings.palette.name())),
+            ("Glyphs", String::from(on_off(self.settings.glyphs))),
+        ];
+        for effect in &Effect::ALL {
+            settings.push((
+                effect.name(),
+                String::from(on_off(self.settings.effects[*effect as usize])),
+            ));
+        }
+        settings
+    }
+
+    fn change_setting(&mut self, forward: bool) {
+        let step = |value: usize, count: usize| match forward {
+            true => (value + 1) % count,
+            false => (value + count - 1) % count,
+        };
+        match self.setting {
+            0 => {
+                self.theme = step(self.theme, self.themes.len());
+                self.settings.theme = self.themes[self.theme].name.clone();
+            }
+            1 => {
+                let palettes = Palette::ALL;
+                let current = palettes.iter().position(|&p| p == self.settings.palette);
+                self.settings.palette = palettes[step(current.unwrap_or(0), palettes.len())];
+            }
+            2 => self.settings.glyphs = !self.settings.glyphs,
+            i => {
+                let effect = Effect::ALL[i - 3] as usize;
+                self.settings.effects[effect] = !self.settings.effects[effect];
+                self.effects.enabled = self.settings.effects;
+                self.rival_effects.enabled = self.settings.effects;
+            }
+        }
+        self.style =
+            self.themes[self.theme].with_access(self.settings.palette, self.settings.glyphs);
+        self.settings.save();
+    }
+
+    async fn settings(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let padding = layout.scaled(DISPLAY_PADDING);
+        layout.draw_centered(
+            SETTINGS_TEXT,
+            screen_width() / 2.0,
+            padding,
+            60.0,
+            theme.text,
+        );
+        let settings = self.get_settings();
+        for (i, (label, value)) in settings.iter().enumerate() {
+            layout.draw_text(
+                format!("{}: < {} >", label, value).as_ref(),
+                padding,
+                padding * 2.0 + i as f32 * layout.scaled(40.0),
+                30.0,
+                if i == self.setting {
+                    theme.accent
+                } else {
+                    theme.text
+                },
+            );
+        }
+
+        // Preview of the pieces and grid in the selected theme
+        let cell = layout.scaled(20.0);
+        let well = Rect::new(
+            padding,
+            padding * 2.0 + (settings.len() as f32 + 0.5) * layout.scaled(40.0),
+            cell * GRID_WIDTH as f32,
+            cell * 4.0,
+        );
+        for (i, c) in "IJLOSTZ".chars().enumerate() {
+            if let Some(id) = BlockType::from_char(c) {
+                let rect = Rect::new(well.x + i as f32 * cell, well.y, cell, cell);
+                theme.draw_block(rect, id.get_color(), 1.0);
+            }
+        }
+        for x in 0..GRID_WIDTH - 1 {
+            let rect = Rect::new(well.x + x as f32 * cell, well.bottom() - cell, cell, cell);
+            theme.draw_block(rect, GREY, 1.0);
+        }
+        theme.draw_grid(well, cell);
+
+        if is_key_pressed(KeyCode::W) {
+            self.setting = self.setting.saturating_sub(1);
+        } else if is_key_pressed(KeyCode::S) {
+            self.setting = (self.setting + 1).min(settings.len() - 1);
+        } else if is_key_pressed(KeyCode::A) {
+            self.change_setting(false);
+        } else if is_key_pressed(KeyCode::D) {
+            self.change_setting(true);
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.state = State::Welcome;
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.quit();
+        }
+        next_frame().await;
+    }
+
+    async fn edit(&mut self) {
+        let layout = self.get_layout();
+        let theme = self.get_theme();
+        let well = layout.well;
+        for block in self.editor.get_board() {
+            let rect = cell_rect(well, block.position.x, block.position.y);
+            theme.draw_block(rect, block.color, 1.0);
+        }
+        theme.draw_grid(well, layout.cell);
+        layout.draw_text(
+            EDITOR_HELP_TEXT,
+            layout.top.x,
+            layout.top.y + layout.top.h * 0.4,
+            20.0,
+            theme.text,
+        );
+        layout.draw_text(
+            self.editor.get_comment(),
+            layout.top.x,
+            layout.top.y + layout.top.h * 0.85,
+            20.0,
+            theme.muted,
+        );
+        let swatch = layout.font(20.0);
+        let step = layout.font(30.0);
+        let y = layout.bottom.y + (layout.bottom.h - swatch) / 2.0;
+        for (i, c) in EDITOR_BRUSHES.chars().enumerate() {
+            let color = BlockType::from_char(c).map_or(GREY, |id| id.get_color());
+            let x = layout.bottom.x + i as f32 * step;
+            theme.draw_block(Rect::new(x, y, swatch, swatch), color, 1.0);
+            if i == self.editor.get_brush() {
+                draw_rectangle_lines(
+                    x - 3.0,
+                    y - 3.0,
+                    swatch + 6.0,
+                    swatch + 6.0,
+                    2.0,
+                    theme.text,
+                );
+            }
+        }
+        let queue: String = self
+            .editor
+            .get_queue()
+            .iter()
+            .map(|id| id.to_char())
+            .collect();
+        let hold: String = self
+            .editor
+            .get_hold()
+            .map(|id| id.to_char())
+            .into_iter()
+            .collect();
+        layout.draw_text(
+            format!("Queue: {}  Hold: {}", queue, hold).as_ref(),
+            layout.bottom.x + EDITOR_BRUSHES.len() as f32 * step + layout.font(10.0),
+            y + swatch,
+            30.0,
+            theme.text,
+        );
+
+        if let Some((x, y)) = cell_at(well, mouse_position().into()) {
+            if is_mouse_button_down(MouseButton::Left) {
+                self.editor.paint(x, y);
+            } else if is_mouse_button_down(MouseButton::Right) {
+                self.editor.erase(x, y);
+            }
+        }
+
+        let control = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let brushes = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+        ];
+        let pieces = [
+            (KeyCode::I, BlockType::I),
+            (KeyCode::J, BlockType::J),
+            (KeyCode::L, BlockType::L),
+            (KeyCode::O, BlockType::O),
+            (KeyCode::S, BlockType::S),
+            (KeyCode::T, BlockType::T),
+            (KeyCode::Z, BlockType::Z),
+        ];
+        if let Some(brush) = brushes.iter().position(|key| is_key_pressed(*key)) {
+            self.editor.set_brush(brush);
+        }
+        if control && is_key_pressed(KeyCode::C) {
+            set_clipboard(&encode(&[self.editor.to_page()]));
+        } else if control && is_key_pressed(KeyCode::V) {
+            match get_clipboard()
+                .ok_or(String::from("clipboard is empty"))
+                .and_then(|text| decode(&text))
+            {
+                Ok(pages) if !pages.is_empty() => self.editor = Editor::from_page(&pages[0]),
+                Ok(_) => (),
+                Err(e) => eprintln!("{}", e),
+            }
+        } else if control && is_key_pressed(KeyCode::S) {
+            save_lines(EDITOR_SAVE, &[self.editor.to_text()]);
+        } else if control && is_key_pressed(KeyCode::L) {
+            match Editor::parse(&load_lines(EDITOR_SAVE).join("\n")) {
+                Ok(editor) => self.editor = editor,
+                Err(e) => eprintln!("{}: {}", EDITOR_SAVE, e),
+            }
+        } else if let Some((_, id)) = pieces.iter().find(|(key, _)| is_key_pressed(*key)) {
+            self.editor.push_queue(id.clone());
+        } else if is_key_pressed(KeyCode::Backspace) {
+            self.editor.pop_queue();
+        } else if is_key_pressed(KeyCode::H) {
+            self.editor.cycle_hold();
+        } else if is_key_pressed(KeyCode::C) {
+            self.editor.clear();
+        } else if is_key_pressed(KeyCode::Space) {
+            self.reset().await;
+            self.state = State::Running;
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.state = State::Welcome;
+        } else if is_key_pressed(KeyCode::Escape) {
+            self.quit();
+        }
+        next_frame().await;
+    }
+
+    fn is_puzzle_over(&mut self) -> bool {
+        let run = match &self.play.puzzle_run {
+            Some(run) => run,
+            None => return false,
+        };
+        if run.is_solved() {
+            let file = &self.puzzles[self.selected].file;
+            if !self.solved.contains(file) {
+                self.solved.push(file.clone());
+                save_lines(PUZZLE_SAVE, &self.solved);
+            }
+        }
+        run.is_solved() || run.is_failed()
+    }
+
+    fn is_opener_mismatch(&mut self) -> bool {
+        let run = match &self.play.opener_run {
+            Some(run) => run,
+            None => return false,
+        };
+        if run.is_complete() {
+            let file = &self.openers[self.selected].file;
+            if !self.learned.contains(file) {
+                self.learned.push(file.clone());
+                save_lines(OPENER_SAVE, &self.learned);
+            }
+        }
+        run.is_mismatch()
+    }
+
     async fn running(&mut self) {
-        let mut last_update = get_time();
-        let start_time = get_time();
+        let mut last_frame = get_time();
+        let start_time = get_time() - self.play.time;
+        let mut frames = (self.play.time * FRAME_RATE) as u64;
 
         loop {
-            self.time = get_time() - start_time;
+            self.get_theme().draw_background();
+            self.play.time = get_time() - start_time;
+            let dt = get_time() - last_frame;
+            last_frame = get_time();
 
+            if is_quit_requested() {
+                self.quit();
+            }
             self.player_input();
 
-            if get_time() - last_update > self.tetrus.get_tick() {
-                last_update = get_time();
-                if !self.tetrus.is_active() {
-                    self.tetrus.spawn_block();
-                } else {
-                    self.tetrus.update_active();
+            if self.play.update_garbage(dt) {
+                self.state = State::GameOver;
+                return;
+            }
+
+            if self.is_opener_mismatch() {
+                frames = (self.play.time * FRAME_RATE) as u64;
+                if is_key_pressed(KeyCode::Enter) {
+                    self.play.retries += 1;
+                    self.reset().await;
+                    return;
+                }
+            }
+            while frames < (self.play.time * FRAME_RATE) as u64
+                && !self.is_opener_mismatch()
+                && !self.play.is_over()
+            {
+                frames += 1;
+                self.update_frame();
+            }
+            let shake = self.effects.shake(self.get_layout().cell)
+                + self.rival_effects.shake(self.get_layout().cell);
+            if shake != Vec2::ZERO {
+                let screen = Rect::new(-shake.x, -shake.y, screen_width(), screen_height());
+                set_camera(&Camera2D::from_display_rect(screen));
+            }
+            if self.play.rival.is_some() {
+                self.draw_versus();
+            } else {
+                self.draw_board();
+                self.draw_score();
+                if self.hud {
+                    self.draw_hud(&self.get_layout());
                 }
             }
-            self.draw_board();
-            self.draw_score();
             self.draw_time();
-            if self.tetrus.is_game_over() {
+            match self.play.mode {
+                Mode::Survival | Mode::Daily => self.draw_garbage_timer(),
+                Mode::Master => self.draw_grade(),
+                Mode::Puzzle => self.draw_puzzle(),
+                Mode::Finesse => self.draw_finesse(),
+                Mode::Opener => self.draw_opener(),
+                Mode::Endless | Mode::Versus | Mode::Cpu | Mode::Editor => (),
+            }
+            set_default_camera();
+            if self.play.is_lost(0)
+                || self.play.master.is_finished()
+                || self.is_puzzle_over()
+                || self.play.is_versus_over()
+            {
                 self.state = State::GameOver;
                 return;
             }
@@ -184,47 +1267,143 @@ impl Game {
     }
 
     async fn game_over(&mut self) {
-        let game_over_size = measure_text(GAME_OVER_TEXT, Some(Font::default()), 100, 1.0);
-        let score_size = measure_text(SCORE_TEXT_PLACEHOLDER, Some(Font::default()), 40, 1.0);
-        let space_size = measure_text(SPACE_TEXT, Some(Font::default()), 20, 1.0);
+        let layout = self.get_layout();
+        self.finish_daily();
+        self.finish_stats();
+        let title = self.play.get_title();
+        let game_over_size = layout.measure(title, 100.0);
+        let score_size = layout.measure(SCORE_TEXT_PLACEHOLDER, 40.0);
+        let space_size = layout.measure(SPACE_TEXT, 20.0);
 
-        draw_text(
-            GAME_OVER_TEXT,
+        if self.play.visibility != Visibility::Normal {
+            self.play.reveal = u64::MAX;
+            self.draw_board();
+        }
+        let theme = self.get_theme();
+        layout.draw_text(
+            title,
             screen_width() / 2.0 - game_over_size.width / 2.0,
             screen_height() / 2.0 - game_over_size.height / 2.0,
             100.0,
-            WHITE,
+            theme.text,
         );
-        draw_text(
-            format!("Score: {:05}", self.tetrus.get_score()).as_ref(),
+        if self.play.mode == Mode::Master {
+            let grade_text = format!("Grade {}", self.play.master.get_grade());
+            let grade_size = layout.measure(&grade_text, 40.0);
+            layout.draw_text(
+                &grade_text,
+                screen_width() / 2.0 - grade_size.width / 2.0,
+                screen_height() / 2.0 - grade_size.height / 2.0 - game_over_size.height,
+                40.0,
+                theme.text,
+            );
+        }
+        let score_text = self.play.get_result();
+        layout.draw_text(
+            &score_text,
             screen_width() / 2.0 - score_size.width / 2.0,
             screen_height() / 2.0 - score_size.height / 2.0 + game_over_size.height / 2.0,
             40.0,
-            WHITE,
+            theme.text,
         );
-        draw_text(
+        layout.draw_text(
             SPACE_TEXT,
             screen_width() / 2.0 - space_size.width / 2.0,
             screen_height() / 2.0 - space_size.height / 2.0 + (game_over_size.height / 2.0) * 2.0,
             20.0,
-            WHITE,
+            theme.text,
         );
+        if self.play.is_practice() && self.play.can_undo() {
+            let undo_size = layout.measure(UNDO_TEXT, 20.0);
+            layout.draw_text(
+                UNDO_TEXT,
+                screen_width() / 2.0 - undo_size.width / 2.0,
+                screen_height() / 2.0 - undo_size.height / 2.0
+                    + (game_over_size.height / 2.0) * 2.5,
+                20.0,
+                theme.text,
+            );
+        }
         if is_key_pressed(KeyCode::Space) {
             self.state = State::Running;
-            self.tetrus = Tetrus::new().await;
+            self.reset().await;
+        } else if is_key_pressed(KeyCode::C) {
+            set_clipboard(&encode(&self.play.pages));
+        } else if is_key_pressed(KeyCode::U) && self.play.is_practice() && self.undo() {
+            self.state = State::Running;
+        } else if is_key_pressed(KeyCode::Enter)
+            && (self.play.mode == Mode::Puzzle || self.play.mode == Mode::Opener)
+        {
+            self.state = State::LevelSelect;
+        } else if is_key_pressed(KeyCode::Enter) && self.play.mode == Mode::Editor {
+            self.state = State::Editor;
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.state = State::Welcome;
         } else if is_key_pressed(KeyCode::Escape) {
-            #[cfg(not(target_arch = "wasm32"))]
-            std::process::exit(0);
+            self.quit();
         }
         next_frame().await
     }
 
     pub async fn run(&mut self) -> bool {
+        self.get_theme().draw_background();
+        if is_quit_requested() {
+            self.quit();
+        }
         match self.state {
             State::Welcome => self.welcome().await,
+            State::LevelSelect => self.level_select().await,
+            State::DailyHistory => self.daily_history().await,
+            State::Stats => self.lifetime_stats().await,
+            State::Settings => self.settings().await,
+            State::Editor => self.edit().await,
             State::Running => self.running().await,
             State::GameOver => self.game_over().await,
         }
         true
     }
 }
+
+// Bar chart of one value per day, starting at `first`.
+fn draw_chart(layout: &Layout, theme: &Theme, title: &str, values: &[f64], first: u64, area: Rect) {
+    let (left, top, width, height) = (area.x, area.y, area.w, area.h);
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    let bottom = top + height - layout.scaled(30.0);
+    let chart_height = height - layout.scaled(80.0);
+    layout.draw_text(
+        format!("{} (max {:.2})", title, max).as_ref(),
+        left,
+        top + layout.scaled(30.0),
+        24.0,
+        theme.muted,
+    );
+    let bar_width = width / values.len() as f32;
+    for (i, value) in values.iter().enumerate() {
+        let bar_height = if max > 0.0 {
+            (value / max) as f32 * chart_height
+        } else {
+            0.0
+        };
+        draw_rectangle(
+            left + i as f32 * bar_width + bar_width * 0.1,
+            bottom - bar_height,
+            bar_width * 0.8,
+            bar_height,
+            theme.accent,
+        );
+    }
+    draw_line(left, bottom, left + width, bottom, 2.0, theme.muted);
+    let last = first + values.len() as u64 - 1;
+    let y = bottom + layout.scaled(20.0);
+    layout.draw_text(&date_string(first)[5..], left, y, 20.0, theme.muted);
+    layout.draw_right(&date_string(last)[5..], left + width, y, 20.0, theme.muted);
+}
+
+// macroquad 0.3 only exposes the clipboard through the miniquad context.
+fn get_clipboard() -> Option<String> {
+    macroquad::miniquad::clipboard::get(unsafe { get_internal_gl() }.quad_context)
+}
+
+fn set_clipboard(text: &str) {
+    macroquad::miniquad::clipboard::set(unsafe { get_internal_gl() }.quad_context, text);
+}
@@ -0,0 +1,139 @@
+use crate::constants::*;
+use macroquad::prelude::*;
+
+// Screen regions for the current window size. Everything is measured in square cells so the
+// well keeps its proportions when the window is resized.
+pub struct Layout {
+    pub cell: f32,
+    pub scale: f32,
+    pub well: Rect,
+    pub rival: Option<Rect>,
+    pub left: Rect,
+    pub right: Rect,
+    pub top: Rect,
+    pub bottom: Rect,
+    pub typeface: Option<Font>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        Self::fit(screen_width(), screen_height(), false)
+    }
+
+    pub fn versus() -> Self {
+        Self::fit(screen_width(), screen_height(), true)
+    }
+
+    pub fn fit(width: f32, height: f32, versus: bool) -> Self {
+        let grid = GRID_WIDTH as f32;
+        let wells = if versus {
+            grid * 2.0 + LAYOUT_GAP
+        } else {
+            grid
+        };
+        let columns = wells + LAYOUT_PANEL * 2.0;
+        let rows = VISIBLE_ROWS as f32 + LAYOUT_MARGIN * 2.0;
+        let cell = (width / columns).min(height / rows).floor().max(1.0);
+        let well = Rect::new(
+            ((width - wells * cell) / 2.0).floor(),
+            ((height - VISIBLE_ROWS as f32 * cell) / 2.0).floor(),
+            grid * cell,
+            VISIBLE_ROWS as f32 * cell,
+        );
+        let rival = versus.then_some(Rect {
+            x: well.x + (grid + LAYOUT_GAP) * cell,
+            ..well
+        });
+        let right = rival.unwrap_or(well).right();
+        let panel = LAYOUT_PANEL * cell;
+        let margin = LAYOUT_MARGIN * cell;
+        let outer = right - well.x + panel * 2.0;
+        Layout {
+            cell,
+            scale: (width / DISPLAY_WIDTH).min(height / DISPLAY_HEIGHT),
+            well,
+            rival,
+            left: Rect::new(well.x - panel, well.y, panel, well.h),
+            right: Rect::new(right, well.y, panel, well.h),
+            top: Rect::new(well.x - panel, well.y - margin, outer, margin),
+            bottom: Rect::new(well.x - panel, well.bottom(), outer, margin),
+            typeface: None,
+        }
+    }
+
+    // Baseline for a mode's status line in the strip below the well.
+    pub fn status_y(&self) -> f32 {
+        self.bottom.y + self.bottom.h * 0.75
+    }
+
+    pub fn scaled(&self, size: f32) -> f32 {
+        size * self.scale
+    }
+
+    pub fn font(&self, size: f32) -> f32 {
+        self.scaled(size).round().max(1.0)
+    }
+
+    pub fn measure(&self, text: &str, size: f32) -> TextDimensions {
+        measure_text(
+            text,
+            Some(self.typeface.unwrap_or_default()),
+            self.font(size) as u16,
+            1.0,
+        )
+    }
+
+    pub fn draw_text(&self, text: &str, x: f32, y: f32, size: f32, color: Color) {
+        draw_text_ex(
+            text,
+            x,
+            y,
+            TextParams {
+                font: self.typeface.unwrap_or_default(),
+                font_size: self.font(size) as u16,
+                color,
+                ..Default::default()
+            },
+        );
+    }
+
+    // Draws text horizontally centered on `x`.
+    pub fn draw_centered(&self, text: &str, x: f32, y: f32, size: f32, color: Color) {
+        let dimensions = self.measure(text, size);
+        self.draw_text(text, x - dimensions.width / 2.0, y, size, color);
+    }
+
+    // Draws text ending at `x`.
+    pub fn draw_right(&self, text: &str, x: f32, y: f32, size: f32, color: Color) {
+        let dimensions = self.measure(text, size);
+        self.draw_text(text, x - dimensions.width, y, size, color);
+    }
+}
+
+// Screen rectangle of a grid cell. The hidden rows above the well land above `well.y`.
+pub fn cell_rect(well: Rect, x: usize, y: usize) -> Rect {
+    let cell = well.w / GRID_WIDTH as f32;
+    Rect::new(
+        well.x + x as f32 * cell,
+        well.y + (y as f32 - HIDDEN_ROWS as f32) * cell,
+        cell,
+        cell,
+    )
+}
+
+pub fn cell_at(well: Rect, point: Vec2) -> Option<(usize, usize)> {
+    if !well.contains(point) {
+        return None;
+    }
+    let cell = well.w / GRID_WIDTH as f32;
+    Some((
+        (((point.x - well.x) / cell) as usize).min(GRID_WIDTH - 1),
+        (((point.y - well.y) / cell) as usize).min(VISIBLE_ROWS - 1) + HIDDEN_ROWS,
+    ))
+}
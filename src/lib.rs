@@ -0,0 +1,31 @@
+pub mod ai;
+pub mod board;
+pub mod constants;
+pub mod daily;
+pub mod editor;
+pub mod effects;
+pub mod eval;
+pub mod finesse;
+pub mod fumen;
+pub mod game;
+pub mod icons;
+pub mod json;
+pub mod layout;
+pub mod master;
+pub mod opener;
+pub mod play;
+pub mod puzzle;
+pub mod report;
+pub mod rng;
+pub mod save;
+pub mod settings;
+pub mod sim;
+pub mod sounds;
+pub mod stats;
+pub mod survival;
+pub mod tbp;
+pub mod tetrus;
+pub mod theme;
+pub mod train;
+pub mod tui;
+pub mod versus;
@@ -1,12 +1,6 @@
 use macroquad::{prelude::*, rand::srand};
-use constants::*;
-use game::*;
-
-mod game;
-mod tetrus;
-mod sounds;
-mod constants;
-mod icons;
+use tetrus::constants::*;
+use tetrus::game::*;
 
 fn get_mq_conf() -> macroquad::prelude::Conf {
     macroquad::prelude::Conf {
@@ -15,6 +9,7 @@ fn get_mq_conf() -> macroquad::prelude::Conf {
         window_width: DISPLAY_WIDTH as i32,
         fullscreen: false,
         window_resizable: true,
+        high_dpi: true,
         icon: Some(ICON),
         ..Default::default()
     }
@@ -0,0 +1,137 @@
+use crate::constants::*;
+use crate::json::*;
+use crate::tetrus::Timing;
+
+pub struct Master {
+    level: u32,
+    score: u32,
+    combo: u32,
+    soft: u32,
+    cleared: bool,
+}
+
+impl Default for Master {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Master {
+    pub fn new() -> Self {
+        Master {
+            level: 0,
+            score: 0,
+            combo: 1,
+            soft: 0,
+            cleared: false,
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("level", Json::Number(self.level as f64)),
+            ("score", Json::Number(self.score as f64)),
+            ("combo", Json::Number(self.combo as f64)),
+            ("soft", Json::Number(self.soft as f64)),
+            ("cleared", Json::Bool(self.cleared)),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> Result<Master, String> {
+        Ok(Master {
+            level: json.get_f64("level")? as u32,
+            score: json.get_f64("score")? as u32,
+            combo: json.get_f64("combo")? as u32,
+            soft: json.get_f64("soft")? as u32,
+            cleared: json.get_bool("cleared")?,
+        })
+    }
+
+    pub fn timing(&self) -> Timing {
+        let gravity = MASTER_GRAVITY
+            .iter()
+            .rev()
+            .find(|(level, _)| self.level >= *level)
+            .map(|(_, gravity)| *gravity)
+            .unwrap_or(GRAVITY_UNIT);
+        let (_, are, lock_delay, clear_delay) = MASTER_DELAYS
+            .iter()
+            .rev()
+            .find(|(level, ..)| self.level >= *level)
+            .copied()
+            .unwrap_or(MASTER_DELAYS[0]);
+        Timing {
+            gravity,
+            soft_gravity: GRAVITY_UNIT,
+            are,
+            lock_delay,
+            clear_delay,
+        }
+    }
+
+    pub fn on_spawn(&mut self) {
+        if !self.cleared {
+            self.combo = 1;
+        }
+        self.cleared = false;
+        self.soft = 0;
+        if self.level % 100 != 99 && self.level != MASTER_MAX_LEVEL - 1 {
+            self.level += 1;
+        }
+    }
+
+    pub fn on_soft_drop(&mut self) {
+        self.soft += 1;
+    }
+
+    pub fn on_clear(&mut self, lines: usize, perfect: bool) {
+        let lines = lines as u32;
+        let bravo = if perfect { 4 } else { 1 };
+        self.cleared = true;
+        self.combo += 2 * lines - 2;
+        self.score += ((self.level + lines).div_ceil(4) + self.soft) * lines * self.combo * bravo;
+        self.level = (self.level + lines).min(MASTER_MAX_LEVEL);
+    }
+
+    pub fn get_level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn get_section(&self) -> u32 {
+        ((self.level / 100 + 1) * 100).min(MASTER_MAX_LEVEL)
+    }
+
+    pub fn get_score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn get_grade(&self) -> &str {
+        if self.is_finished() && self.score >= MASTER_GM_SCORE {
+            return "GM";
+        }
+        MASTER_GRADES
+            .iter()
+            .rev()
+            .find(|(score, _)| self.score >= *score)
+            .map(|(_, grade)| *grade)
+            .unwrap_or("9")
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.level >= MASTER_MAX_LEVEL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn section_stops_at_the_max_level() {
+        let mut master = Master::new();
+        for (level, section) in [(0, 100), (899, 900), (900, 999), (999, 999)] {
+            master.level = level;
+            assert_eq!(master.get_section(), section);
+        }
+    }
+}
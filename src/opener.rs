@@ -0,0 +1,179 @@
+use crate::constants::*;
+use crate::report::*;
+use crate::tetrus::*;
+use macroquad::file::load_string;
+
+#[derive(Clone)]
+pub struct Opener {
+    pub file: String,
+    pub name: String,
+    pub queue: Vec<BlockType>,
+    pub stages: Vec<Vec<Block>>,
+}
+
+fn parse_target(rows: &[&str]) -> Result<Vec<Block>, String> {
+    if rows.is_empty() {
+        return Err(String::from("empty target"));
+    }
+    if rows.len() > GRID_HEIGHT - 4 {
+        return Err(String::from("target is taller than the well"));
+    }
+    let mut target = Vec::new();
+    let top = GRID_HEIGHT - rows.len();
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != GRID_WIDTH {
+            return Err(format!("target row must be {} cells: {}", GRID_WIDTH, row));
+        }
+        if !row.contains('.') {
+            return Err(format!("target row must not be full: {}", row));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let id = match c {
+                '.' => continue,
+                c => BlockType::from_char(c).ok_or(format!("invalid cell: {}", c))?,
+            };
+            target.push(Block {
+                position: Position::new((x, top + y)),
+                color: id.get_color(),
+                locked_at: 0,
+            });
+        }
+    }
+    Ok(target)
+}
+
+impl Opener {
+    pub fn parse(file: &str, text: &str) -> Result<Opener, String> {
+        let mut name = String::from(file);
+        let mut queue = Vec::new();
+        let mut stages: Vec<Vec<&str>> = Vec::new();
+
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if line == "target:" {
+                stages.push(Vec::new());
+                continue;
+            }
+            if let Some(rows) = stages.last_mut() {
+                rows.push(line);
+                continue;
+            }
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("expected `key: value`, found: {}", line)),
+            };
+            match key {
+                "name" => name = String::from(value),
+                "queue" => {
+                    for c in value.chars().filter(|c| !c.is_whitespace()) {
+                        match BlockType::from_char(c) {
+                            Some(id) => queue.push(id),
+                            None => return Err(format!("invalid piece in queue: {}", c)),
+                        }
+                    }
+                }
+                _ => return Err(format!("unknown key: {}", key)),
+            }
+        }
+
+        if queue.is_empty() {
+            return Err(String::from("missing queue"));
+        }
+        if stages.is_empty() {
+            return Err(String::from("missing target"));
+        }
+        Ok(Opener {
+            file: String::from(file),
+            name,
+            queue,
+            stages: stages
+                .iter()
+                .map(|rows| parse_target(rows))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub async fn load_all() -> Vec<Opener> {
+        let mut openers = Vec::new();
+        let index = match load_string(&format!("{}/index.txt", OPENER_DIR)).await {
+            Ok(index) => index,
+            Err(_) => return openers,
+        };
+        for file in index.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if let Ok(text) = load_string(&format!("{}/{}", OPENER_DIR, file)).await {
+                match Opener::parse(file, &text) {
+                    Ok(opener) => openers.push(opener),
+                    Err(e) => report(format!("{}: {}", file, e)),
+                }
+            }
+        }
+        openers
+    }
+}
+
+#[derive(Clone)]
+pub struct OpenerRun {
+    stages: Vec<Vec<Block>>,
+    stage: usize,
+    built: bool,
+    mismatch: bool,
+}
+
+impl OpenerRun {
+    pub fn new(opener: &Opener) -> Self {
+        OpenerRun {
+            stages: opener.stages.clone(),
+            stage: 0,
+            built: false,
+            mismatch: false,
+        }
+    }
+
+    pub fn on_lock(&mut self, piece: &[Block], inactive: &[Block], cleared: bool) {
+        if self.is_complete() {
+            return;
+        }
+        if self.built {
+            self.mismatch |= !cleared;
+            self.stage += 1;
+            self.built = false;
+            return;
+        }
+        let target = &self.stages[self.stage];
+        let fits = piece.iter().all(|block| {
+            target
+                .iter()
+                .any(|n| n.position == block.position && n.color == block.color)
+        });
+        let wanted = piece.iter().any(|block| {
+            target.iter().any(|n| {
+                n.color == block.color && !inactive.iter().any(|b| b.position == n.position)
+            })
+        });
+        let covers = piece
+            .iter()
+            .any(|block| target.iter().any(|n| n.position == block.position));
+        self.mismatch |= !fits && (wanted || covers);
+        self.built = !self.mismatch
+            && target
+                .iter()
+                .all(|n| inactive.iter().any(|block| block.position == n.position));
+    }
+
+    pub fn get_remaining<'a>(&'a self, inactive: &'a [Block]) -> impl Iterator<Item = &'a Block> {
+        self.stages[self.stage]
+            .iter()
+            .filter(|n| !inactive.iter().any(|block| block.position == n.position))
+    }
+
+    pub fn get_stage(&self) -> (usize, usize) {
+        (self.stage + 1, self.stages.len())
+    }
+
+    pub fn is_mismatch(&self) -> bool {
+        self.mismatch
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.built && self.stage + 1 == self.stages.len()
+    }
+}
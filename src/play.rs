@@ -0,0 +1,586 @@
+use crate::ai::*;
+use crate::constants::*;
+use crate::daily::*;
+use crate::finesse::*;
+use crate::fumen::*;
+use crate::json::*;
+use crate::master::*;
+use crate::opener::*;
+use crate::puzzle::*;
+use crate::stats::*;
+use crate::survival::*;
+use crate::tetrus::*;
+use crate::versus::*;
+use macroquad::rand::rand;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Endless,
+    Survival,
+    Master,
+    Puzzle,
+    Daily,
+    Versus,
+    Cpu,
+    Finesse,
+    Opener,
+    Editor,
+}
+
+impl Mode {
+    pub fn name(&self) -> &str {
+        match self {
+            Mode::Endless => "Endless",
+            Mode::Survival => "Survival",
+            Mode::Master => "Master",
+            Mode::Puzzle => "Puzzle",
+            Mode::Daily => "Daily",
+            Mode::Versus => "Versus",
+            Mode::Cpu => "Vs CPU",
+            Mode::Finesse => "Finesse",
+            Mode::Opener => "Openers",
+            Mode::Editor => "Editor",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Mode> {
+        let mut mode = Mode::Endless;
+        loop {
+            if mode.name() == name {
+                return Some(mode);
+            }
+            mode = mode.next();
+            if mode == Mode::Endless {
+                return None;
+            }
+        }
+    }
+
+    pub fn next(&self) -> Mode {
+        match self {
+            Mode::Endless => Mode::Survival,
+            Mode::Survival => Mode::Master,
+            Mode::Master => Mode::Puzzle,
+            Mode::Puzzle => Mode::Daily,
+            Mode::Daily => Mode::Versus,
+            Mode::Versus => Mode::Cpu,
+            Mode::Cpu => Mode::Finesse,
+            Mode::Finesse => Mode::Opener,
+            Mode::Opener => Mode::Editor,
+            Mode::Editor => Mode::Endless,
+        }
+    }
+
+    pub fn prev(&self) -> Mode {
+        match self {
+            Mode::Endless => Mode::Editor,
+            Mode::Survival => Mode::Endless,
+            Mode::Master => Mode::Survival,
+            Mode::Puzzle => Mode::Master,
+            Mode::Daily => Mode::Puzzle,
+            Mode::Versus => Mode::Daily,
+            Mode::Cpu => Mode::Versus,
+            Mode::Finesse => Mode::Cpu,
+            Mode::Opener => Mode::Finesse,
+            Mode::Editor => Mode::Opener,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Normal,
+    Fading,
+    Invisible,
+}
+
+impl Visibility {
+    pub fn name(&self) -> &str {
+        match self {
+            Visibility::Normal => "Normal",
+            Visibility::Fading => "Fading",
+            Visibility::Invisible => "Invisible",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Visibility> {
+        [
+            Visibility::Normal,
+            Visibility::Fading,
+            Visibility::Invisible,
+        ]
+        .into_iter()
+        .find(|visibility| visibility.name() == name)
+    }
+
+    pub fn next(&self) -> Visibility {
+        match self {
+            Visibility::Normal => Visibility::Fading,
+            Visibility::Fading => Visibility::Invisible,
+            Visibility::Invisible => Visibility::Normal,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Snapshot {
+    tetrus: Tetrus,
+    finesse: Finesse,
+    puzzle_run: Option<PuzzleRun>,
+    opener_run: Option<OpenerRun>,
+    pages: Vec<Page>,
+}
+
+// One game in progress and the rules of its mode. Front-ends own input, drawing, sound and
+// anything kept between games.
+pub struct Play {
+    pub tetrus: Tetrus,
+    pub rival: Option<Tetrus>,
+    pub bot: Option<Box<dyn Controller>>,
+    pub versus: Versus,
+    pub survival: Survival,
+    pub master: Master,
+    pub finesse: Finesse,
+    pub time: f64,
+    pub mode: Mode,
+    pub visibility: Visibility,
+    pub reveal: u64,
+    pub puzzle_run: Option<PuzzleRun>,
+    pub opener_run: Option<OpenerRun>,
+    pub retries: u32,
+    pub pages: Vec<Page>,
+    // Day of the Daily being played, when it is the first attempt and so counts
+    pub daily: Option<u64>,
+    pub session: Stats,
+    spawned: Option<Snapshot>,
+    history: Vec<Snapshot>,
+    future: Vec<(Snapshot, Option<Snapshot>)>,
+}
+
+impl Default for Play {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Play {
+    pub fn new() -> Self {
+        Play {
+            tetrus: Tetrus::new(),
+            rival: None,
+            bot: None,
+            versus: Versus::new(0),
+            survival: Survival::new(SurvivalSettings::default(), 0),
+            master: Master::new(),
+            finesse: Finesse::new(),
+            time: 0.0,
+            mode: Mode::Endless,
+            visibility: Visibility::Normal,
+            reveal: 0,
+            puzzle_run: None,
+            opener_run: None,
+            retries: 0,
+            pages: Vec::new(),
+            daily: None,
+            session: Stats::new(),
+            spawned: None,
+            history: Vec::new(),
+            future: Vec::new(),
+        }
+    }
+
+    // Starts a new game of the current mode. Puzzles, openers and edited positions are set up
+    // afterwards with `set_puzzle`, `set_opener` and `set_position`.
+    pub fn reset(&mut self, survival: &SurvivalSettings, bot: Option<Box<dyn Controller>>) {
+        self.tetrus = Tetrus::new();
+        self.time = 0.0;
+        self.survival = Survival::new(survival.clone(), rand() as u64);
+        self.master = Master::new();
+        self.finesse = Finesse::new();
+        self.reveal = 0;
+        self.puzzle_run = None;
+        self.opener_run = None;
+        self.spawned = None;
+        self.pages.clear();
+        self.history.clear();
+        self.future.clear();
+        self.rival = None;
+        self.bot = None;
+        if self.mode == Mode::Versus || self.mode == Mode::Cpu {
+            let seed = rand() as u64;
+            let mut rival = Tetrus::new();
+            rival.set_seed(seed);
+            self.tetrus.set_seed(seed);
+            self.rival = Some(rival);
+            self.versus = Versus::new(rand() as u64);
+        }
+        if self.mode == Mode::Cpu {
+            self.bot = bot;
+        }
+        if self.mode == Mode::Daily {
+            let day = today();
+            self.tetrus.set_seed(seed(day));
+            self.survival = Survival::new(SurvivalSettings::default(), seed(day) ^ 1);
+        }
+    }
+
+    pub fn set_puzzle(&mut self, puzzle: &Puzzle) {
+        self.tetrus.set_board(puzzle.board.clone());
+        self.tetrus.set_queue(puzzle.queue.clone());
+        self.puzzle_run = Some(PuzzleRun::new(puzzle));
+    }
+
+    pub fn set_opener(&mut self, opener: &Opener) {
+        self.tetrus.set_upcoming(opener.queue.clone());
+        self.opener_run = Some(OpenerRun::new(opener));
+    }
+
+    pub fn set_position(&mut self, board: &[Block], queue: &[BlockType], hold: Option<&BlockType>) {
+        self.tetrus.set_board(board.to_vec());
+        self.tetrus.set_hold(hold.cloned());
+        if !queue.is_empty() {
+            self.tetrus.set_upcoming(queue.to_vec());
+        }
+    }
+
+    pub fn is_saveable(&self) -> bool {
+        matches!(
+            self.mode,
+            Mode::Endless | Mode::Survival | Mode::Master | Mode::Daily
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("mode", Json::string(self.mode.name())),
+            ("visibility", Json::string(self.visibility.name())),
+            ("time", Json::Number(self.time)),
+            (
+                "daily",
+                self.daily
+                    .map_or(Json::Null, |day| Json::Number(day as f64)),
+            ),
+            ("tetrus", self.tetrus.to_json()),
+            ("survival", self.survival.to_json()),
+            ("master", self.master.to_json()),
+            ("session", Json::string(&self.session.to_line())),
+        ])
+    }
+
+    pub fn resume(&mut self, json: &Json) -> Result<(), String> {
+        let get = |key| json.get(key).ok_or(format!("missing {}", key));
+        let mode = Mode::parse(json.get_str("mode")?).ok_or("invalid mode")?;
+        let visibility =
+            Visibility::parse(json.get_str("visibility")?).ok_or("invalid visibility")?;
+        let tetrus = Tetrus::from_json(get("tetrus")?)?;
+        let survival = Survival::from_json(get("survival")?)?;
+        let master = Master::from_json(get("master")?)?;
+        self.time = json.get_f64("time")?;
+        self.daily = match json.get("daily") {
+            Some(daily) => daily.as_f64().map(|day| day as u64),
+            // Saves from before the day was stored
+            None => json.get_bool("daily_counted")?.then(today),
+        };
+        self.mode = mode;
+        self.visibility = visibility;
+        self.reveal = tetrus.get_frame() + (REVEAL_TIME * FRAME_RATE) as u64;
+        self.tetrus = tetrus;
+        self.survival = survival;
+        self.master = master;
+        self.session = json
+            .get_str("session")
+            .ok()
+            .and_then(Stats::parse)
+            .unwrap_or_default();
+        self.finesse = Finesse::new();
+        self.puzzle_run = None;
+        self.opener_run = None;
+        self.spawned = None;
+        self.pages.clear();
+        self.history.clear();
+        self.future.clear();
+        self.rival = None;
+        self.bot = None;
+        Ok(())
+    }
+
+    pub fn is_practice(&self) -> bool {
+        matches!(
+            self.mode,
+            Mode::Puzzle | Mode::Finesse | Mode::Opener | Mode::Editor
+        )
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tetrus: self.tetrus.clone(),
+            finesse: self.finesse.clone(),
+            puzzle_run: self.puzzle_run.clone(),
+            opener_run: self.opener_run.clone(),
+            pages: self.pages.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.tetrus = snapshot.tetrus;
+        self.finesse = snapshot.finesse;
+        self.puzzle_run = snapshot.puzzle_run;
+        self.opener_run = snapshot.opener_run;
+        self.pages = snapshot.pages;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let snapshot = match self.history.pop() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.future.push((self.snapshot(), self.spawned.take()));
+        self.restore(snapshot.clone());
+        self.spawned = Some(snapshot);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let (snapshot, spawned) = match self.future.pop() {
+            Some(future) => future,
+            None => return false,
+        };
+        if let Some(previous) = self.spawned.take() {
+            self.history.push(previous);
+        }
+        self.restore(snapshot);
+        self.spawned = spawned;
+        true
+    }
+
+    pub fn get_score(&self) -> u32 {
+        match self.mode {
+            Mode::Master => self.master.get_score(),
+            _ => self.tetrus.get_score(),
+        }
+    }
+
+    // Counts a key press towards finesse and the session's KPP.
+    pub fn on_key(&mut self) {
+        self.finesse.on_press();
+        self.session.on_key();
+    }
+
+    // Advances both boards by one frame and returns the player's and the rival's events.
+    pub fn update_frame(&mut self, soft_drop: bool) -> (Vec<Event>, Vec<Event>) {
+        let timing = match self.mode {
+            Mode::Master => self.master.timing(),
+            _ => Timing::from_tick(self.tetrus.get_tick()),
+        };
+        self.tetrus.set_timing(timing);
+        self.tetrus.update();
+        if self.mode == Mode::Master && soft_drop && self.tetrus.is_active() {
+            self.master.on_soft_drop();
+        }
+        let events = self.tetrus.take_events();
+        self.session.on_events(&events);
+        let mut rival_events = Vec::new();
+        if let Some(rival) = &mut self.rival {
+            if let Some(bot) = &mut self.bot {
+                bot.update(rival);
+            }
+            rival.set_timing(Timing::from_tick(rival.get_tick()));
+            rival.update();
+            rival_events = rival.take_events();
+            // A top-out is recorded in `versus` and read back through `is_lost`
+            self.versus.on_events(0, &events, &mut self.tetrus);
+            self.versus.on_events(1, &rival_events, rival);
+        }
+        let cleared = events.iter().any(|e| matches!(e, Event::Clear(_)));
+        for event in &events {
+            match event {
+                Event::Spawn => {
+                    self.finesse.on_spawn(&self.tetrus);
+                    if self.mode == Mode::Master {
+                        self.master.on_spawn();
+                    }
+                    if self.is_practice() {
+                        self.spawned = Some(self.snapshot());
+                    }
+                }
+                // The swapped-in piece is measured from the top like a new one
+                Event::Hold => self.finesse.on_spawn(&self.tetrus),
+                Event::Lock => {
+                    self.pages.push(Page::from_lock(&self.tetrus));
+                    if let Some(snapshot) = self.spawned.take() {
+                        self.history.push(snapshot);
+                        self.future.clear();
+                    }
+                    if let Some(run) = &mut self.puzzle_run {
+                        run.on_lock();
+                    }
+                    self.finesse.on_lock(&self.tetrus);
+                    if let Some(run) = &mut self.opener_run {
+                        run.on_lock(self.tetrus.get_last_piece(), &self.tetrus.inactive, cleared);
+                    }
+                }
+                Event::Clear(clear) => {
+                    self.reveal = self.tetrus.get_frame() + (REVEAL_TIME * FRAME_RATE) as u64;
+                    if self.mode == Mode::Master {
+                        self.master.on_clear(clear.lines, clear.perfect);
+                    }
+                    if let Some(run) = &mut self.puzzle_run {
+                        run.on_clear(clear.lines, clear.perfect, clear.tspin);
+                    }
+                }
+            }
+        }
+        (events, rival_events)
+    }
+
+    // Raises survival garbage, returning true if it tops the player out.
+    pub fn update_garbage(&mut self, dt: f64) -> bool {
+        if self.mode != Mode::Survival && self.mode != Mode::Daily {
+            return false;
+        }
+        match self.survival.update(dt) {
+            Some(hole) => self.tetrus.raise_garbage(hole),
+            None => false,
+        }
+    }
+
+    pub fn get_alpha(&self, tetrus: &Tetrus, block: &Block) -> f32 {
+        let frame = tetrus.get_frame();
+        if self.visibility == Visibility::Normal || frame < self.reveal {
+            return 1.0;
+        }
+        match self.visibility {
+            Visibility::Fading => {
+                let age = (frame - block.locked_at) as f64 / FRAME_RATE;
+                (1.0 - age / FADE_TIME).max(0.0) as f32
+            }
+            _ => 0.0,
+        }
+    }
+
+    // Whether player 0, or the rival as player 1, has topped out, including under versus garbage.
+    pub fn is_lost(&self, player: usize) -> bool {
+        let tetrus = match player {
+            0 => &self.tetrus,
+            _ => match &self.rival {
+                Some(rival) => rival,
+                None => return false,
+            },
+        };
+        tetrus.is_game_over() || (self.rival.is_some() && self.versus.is_topped_out(player))
+    }
+
+    pub fn is_versus_over(&self) -> bool {
+        self.is_lost(1)
+    }
+
+    pub fn is_opener_mismatch(&self) -> bool {
+        self.opener_run
+            .as_ref()
+            .is_some_and(|run| run.is_mismatch())
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.is_lost(0)
+            || self.master.is_finished()
+            || self.is_versus_over()
+            || self
+                .puzzle_run
+                .as_ref()
+                .is_some_and(|run| run.is_solved() || run.is_failed())
+    }
+
+    // Live numbers for the HUD.
+    pub fn get_hud(&self) -> Vec<(&str, String)> {
+        let mut live = self.session.clone();
+        live.time = self.time;
+        let level = match self.mode {
+            Mode::Master => self.master.get_level(),
+            _ => self.tetrus.get_level(),
+        };
+        let combo = self.tetrus.get_combo().saturating_sub(1);
+        let mut items = vec![
+            ("PPS", format!("{:.2}", live.pps())),
+            ("APM", format!("{:.1}", live.apm())),
+            ("KPP", format!("{:.2}", live.kpp())),
+            ("Lines", live.lines.to_string()),
+            ("Level", level.to_string()),
+            ("Combo", combo.to_string()),
+            ("Faults", self.finesse.get_faults().to_string()),
+        ];
+        if self.tetrus.is_b2b() {
+            items.push(("B2B", String::from("ready")));
+        }
+        items
+    }
+
+    pub fn get_title(&self) -> &'static str {
+        match (&self.puzzle_run, &self.rival) {
+            (Some(run), _) if run.is_solved() => SOLVED_TEXT,
+            (Some(_), _) => FAILED_TEXT,
+            (None, Some(_)) => match (self.is_lost(0), self.is_lost(1)) {
+                (true, false) if self.bot.is_some() => CPU_WINS_TEXT,
+                (true, false) => P2_WINS_TEXT,
+                (false, true) => P1_WINS_TEXT,
+                _ => DRAW_TEXT,
+            },
+            (None, None) => GAME_OVER_TEXT,
+        }
+    }
+
+    pub fn get_result(&self) -> String {
+        match self.mode {
+            Mode::Versus | Mode::Cpu => format!(
+                "Sent: {} - {}",
+                self.versus.get_sent(0),
+                self.versus.get_sent(1)
+            ),
+            Mode::Finesse => format!(
+                "Faults: {} / {}",
+                self.finesse.get_faults(),
+                self.finesse.get_pieces()
+            ),
+            _ => format!("Score: {:05}", self.get_score()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames_until(play: &mut Play, event: Event) {
+        for _ in 0..10000 {
+            if play.update_frame(false).0.contains(&event) {
+                return;
+            }
+        }
+        panic!("event never happened");
+    }
+
+    fn drop_piece(play: &mut Play) {
+        play.on_key();
+        play.finesse.on_drop();
+        play.tetrus.player_move(Movement::Drop);
+        frames_until(play, Event::Lock);
+    }
+
+    #[test]
+    fn presses_during_are_are_not_counted() {
+        let mut play = Play::new();
+        play.mode = Mode::Master;
+        frames_until(&mut play, Event::Spawn);
+        drop_piece(&mut play);
+        assert!(!play.tetrus.is_active());
+        // A key pressed while the next piece is on its way
+        play.on_key();
+        frames_until(&mut play, Event::Spawn);
+        drop_piece(&mut play);
+        let result = play.finesse.get_last().unwrap();
+        assert_eq!((result.presses, result.minimum), (1, 1));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::board::*;
+use crate::constants::*;
+use crate::report::*;
+use crate::tetrus::*;
+use macroquad::file::load_string;
+
+#[derive(Clone, PartialEq)]
+pub enum Goal {
+    Lines(usize),
+    PerfectClear,
+    TSpin(usize),
+}
+
+impl Goal {
+    fn parse(text: &str) -> Result<Goal, String> {
+        let mut words = text.split_whitespace();
+        let kind = words.next().unwrap_or_default();
+        let count = words.next().map(|n| n.parse::<usize>());
+        match (kind, count) {
+            ("lines", Some(Ok(n))) => Ok(Goal::Lines(n)),
+            ("perfect", None) => Ok(Goal::PerfectClear),
+            ("tspin", Some(Ok(n))) if (1..=3).contains(&n) => Ok(Goal::TSpin(n)),
+            _ => Err(format!("invalid goal: {}", text)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Goal::Lines(n) => format!("Clear {} lines", n),
+            Goal::PerfectClear => String::from("Perfect clear"),
+            Goal::TSpin(1) => String::from("T-spin single"),
+            Goal::TSpin(2) => String::from("T-spin double"),
+            Goal::TSpin(_) => String::from("T-spin triple"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Puzzle {
+    pub file: String,
+    pub name: String,
+    pub goal: Goal,
+    pub pieces: usize,
+    pub queue: Vec<BlockType>,
+    pub board: Vec<Block>,
+}
+
+impl Puzzle {
+    pub fn parse(file: &str, text: &str) -> Result<Puzzle, String> {
+        let mut name = String::from(file);
+        let mut goal = None;
+        let mut pieces = None;
+        let mut queue = Vec::new();
+        let mut rows: Vec<&str> = Vec::new();
+        let mut in_board = false;
+
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if in_board {
+                rows.push(line);
+                continue;
+            }
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("expected `key: value`, found: {}", line)),
+            };
+            match key {
+                "name" => name = String::from(value),
+                "goal" => goal = Some(Goal::parse(value)?),
+                "pieces" => pieces = value.parse::<usize>().ok(),
+                "queue" => {
+                    for c in value.chars().filter(|c| !c.is_whitespace()) {
+                        match BlockType::from_char(c) {
+                            Some(id) => queue.push(id),
+                            None => return Err(format!("invalid piece in queue: {}", c)),
+                        }
+                    }
+                }
+                "board" => in_board = true,
+                _ => return Err(format!("unknown key: {}", key)),
+            }
+        }
+
+        let board = parse_board(&rows)?;
+        let goal = goal.ok_or("missing goal")?;
+        if queue.is_empty() {
+            return Err(String::from("missing queue"));
+        }
+        let pieces = pieces.unwrap_or(queue.len()).min(queue.len());
+        Ok(Puzzle {
+            file: String::from(file),
+            name,
+            goal,
+            pieces,
+            queue,
+            board,
+        })
+    }
+
+    pub async fn load_all() -> Vec<Puzzle> {
+        let mut puzzles = Vec::new();
+        let index = match load_string(&format!("{}/index.txt", PUZZLE_DIR)).await {
+            Ok(index) => index,
+            Err(_) => return puzzles,
+        };
+        for file in index.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if let Ok(text) = load_string(&format!("{}/{}", PUZZLE_DIR, file)).await {
+                match Puzzle::parse(file, &text) {
+                    Ok(puzzle) => puzzles.push(puzzle),
+                    Err(e) => report(format!("{}: {}", file, e)),
+                }
+            }
+        }
+        puzzles
+    }
+}
+
+#[derive(Clone)]
+pub struct PuzzleRun {
+    goal: Goal,
+    pieces: usize,
+    placed: usize,
+    lines: usize,
+    solved: bool,
+}
+
+impl PuzzleRun {
+    pub fn new(puzzle: &Puzzle) -> Self {
+        PuzzleRun {
+            goal: puzzle.goal.clone(),
+            pieces: puzzle.pieces,
+            placed: 0,
+            lines: 0,
+            solved: false,
+        }
+    }
+
+    pub fn on_lock(&mut self) {
+        self.placed += 1;
+    }
+
+    pub fn on_clear(&mut self, lines: usize, perfect: bool, tspin: bool) {
+        self.lines += lines;
+        self.solved |= match self.goal {
+            Goal::Lines(n) => self.lines >= n,
+            Goal::PerfectClear => perfect,
+            Goal::TSpin(n) => tspin && lines == n,
+        };
+    }
+
+    pub fn get_remaining(&self) -> usize {
+        self.pieces - self.placed
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.solved
+    }
+
+    pub fn is_failed(&self) -> bool {
+        !self.solved && self.placed >= self.pieces
+    }
+}
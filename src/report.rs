@@ -0,0 +1,42 @@
+use std::sync::{Mutex, MutexGuard};
+
+// Messages held back while the terminal front-end owns the screen, where stderr would land in
+// the middle of the frame.
+static HELD: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+fn held() -> MutexGuard<'static, Option<Vec<String>>> {
+    HELD.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Prints an error to stderr, or holds it until `release_reports` if `hold_reports` was called.
+pub fn report(message: String) {
+    match held().as_mut() {
+        Some(messages) => messages.push(message),
+        None => eprintln!("{}", message),
+    }
+}
+
+pub fn hold_reports() {
+    held().get_or_insert_with(Vec::new);
+}
+
+pub fn release_reports() {
+    for message in held().take().unwrap_or_default() {
+        eprintln!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_reports_are_released_once() {
+        hold_reports();
+        report(String::from("held"));
+        let message = String::from("held");
+        assert!(held().as_ref().is_some_and(|held| held.contains(&message)));
+        release_reports();
+        assert!(held().is_none());
+    }
+}
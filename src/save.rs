@@ -0,0 +1,36 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::constants::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::report::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_lines(name: &str) -> Vec<String> {
+    match std::fs::read_to_string(format!("{}/{}", SAVE_DIR, name)) {
+        Ok(text) => text.lines().map(String::from).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_lines(name: &str, lines: &[String]) {
+    let _ = std::fs::create_dir_all(SAVE_DIR);
+    if let Err(e) = std::fs::write(format!("{}/{}", SAVE_DIR, name), lines.join("\n")) {
+        report(format!("Failed to save {}: {}", name, e));
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove_save(name: &str) {
+    let _ = std::fs::remove_file(format!("{}/{}", SAVE_DIR, name));
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load_lines(_name: &str) -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_lines(_name: &str, _lines: &[String]) {}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove_save(_name: &str) {}
@@ -0,0 +1,69 @@
+use crate::constants::*;
+use crate::effects::*;
+use crate::save::*;
+use crate::theme::*;
+
+pub struct Settings {
+    pub theme: String,
+    pub palette: Palette,
+    pub glyphs: bool,
+    // Indexed by Effect
+    pub effects: [bool; 5],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Settings {
+            theme: String::from("Classic"),
+            palette: Palette::Theme,
+            glyphs: false,
+            effects: [true; 5],
+        }
+    }
+
+    pub fn load() -> Self {
+        let mut settings = Settings::new();
+        for line in load_lines(SETTINGS_SAVE) {
+            if let Some((key, value)) = line.split_once(':') {
+                settings.set(key.trim(), value.trim());
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let mut lines = vec![
+            format!("theme: {}", self.theme),
+            format!("palette: {}", self.palette.name()),
+            format!("glyphs: {}", on_off(self.glyphs)),
+        ];
+        for effect in Effect::ALL {
+            lines.push(format!(
+                "{}: {}",
+                effect.key(),
+                on_off(self.effects[effect as usize])
+            ));
+        }
+        save_lines(SETTINGS_SAVE, &lines);
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "theme" => self.theme = String::from(value),
+            "palette" => self.palette = Palette::parse(value).unwrap_or(Palette::Theme),
+            "glyphs" => self.glyphs = value == "on",
+            _ => {
+                if let Some(effect) = Effect::ALL.into_iter().find(|e| e.key() == key) {
+                    self.effects[effect as usize] = value == "on";
+                }
+            }
+        }
+    }
+}
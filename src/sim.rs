@@ -0,0 +1,207 @@
+use crate::ai::*;
+use crate::constants::*;
+use crate::eval::*;
+use crate::json::*;
+use crate::tbp::*;
+use crate::tetrus::*;
+
+pub enum Player {
+    Bot(Difficulty, Weights),
+    Tbp(String),
+    Script(Vec<Vec<Movement>>),
+}
+
+impl Player {
+    pub fn parse(text: &str) -> Result<Player, String> {
+        if let Some(command) = text.strip_prefix("tbp:") {
+            return Ok(Player::Tbp(String::from(command)));
+        }
+        if let Some(file) = text.strip_prefix("script:") {
+            let script = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+            return parse_script(&script).map(Player::Script);
+        }
+        Difficulty::parse(text)
+            .map(|difficulty| Player::Bot(difficulty, Weights::default()))
+            .ok_or(format!("unknown player: {}", text))
+    }
+
+    fn controller(&self, seed: u64) -> Result<Box<dyn Controller>, String> {
+        Ok(match self {
+            Player::Bot(difficulty, weights) => {
+                let mut bot = Bot::new(*difficulty, seed);
+                bot.set_weights(*weights);
+                Box::new(bot)
+            }
+            // The built-in bot finishes the game if the external one fails or stops answering
+            Player::Tbp(command) => {
+                Box::new(TbpBot::new(command)?.with_fallback(Bot::new(Difficulty::Expert, seed)))
+            }
+            Player::Script(moves) => Box::new(Script::new(moves.clone())),
+        })
+    }
+}
+
+fn parse_script(text: &str) -> Result<Vec<Vec<Movement>>, String> {
+    let mut pieces = Vec::new();
+    for line in text.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut moves = Vec::new();
+        for c in line.chars().filter(|c| !c.is_whitespace()) {
+            moves.push(match c {
+                'L' => Movement::Left,
+                'R' => Movement::Right,
+                'C' => Movement::Rotate,
+                'D' => Movement::Drop,
+                c => return Err(format!("invalid move in script: {}", c)),
+            });
+        }
+        if moves.last() != Some(&Movement::Drop) {
+            moves.push(Movement::Drop);
+        }
+        pieces.push(moves);
+    }
+    if pieces.is_empty() {
+        return Err(String::from("script is empty"));
+    }
+    Ok(pieces)
+}
+
+pub struct Script {
+    moves: Vec<Vec<Movement>>,
+    index: usize,
+    piece: u32,
+}
+
+impl Script {
+    pub fn new(moves: Vec<Vec<Movement>>) -> Self {
+        Script {
+            moves,
+            index: 0,
+            piece: 0,
+        }
+    }
+}
+
+impl Controller for Script {
+    fn update(&mut self, tetrus: &mut Tetrus) {
+        if !tetrus.is_active() || tetrus.get_pieces() == self.piece {
+            return;
+        }
+        self.piece = tetrus.get_pieces();
+        for movement in &self.moves[self.index % self.moves.len()] {
+            tetrus.player_move(*movement);
+        }
+        self.index += 1;
+    }
+}
+
+pub fn clear_type(clear: &LineClear) -> usize {
+    if clear.tspin {
+        3 + clear.lines.min(3)
+    } else {
+        clear.lines.min(4) - 1
+    }
+}
+
+pub struct GameStats {
+    pub seed: u64,
+    pub score: u32,
+    pub lines: usize,
+    pub pieces: u32,
+    pub frames: u64,
+    pub clears: [u32; CLEAR_TYPES.len()],
+}
+
+impl GameStats {
+    pub fn get_time(&self) -> f64 {
+        self.frames as f64 / FRAME_RATE
+    }
+
+    pub fn csv_header() -> String {
+        format!("seed,score,lines,pieces,time,{}", CLEAR_TYPES.join(","))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let clears: Vec<String> = self.clears.iter().map(|n| n.to_string()).collect();
+        format!(
+            "{},{},{},{},{:.2},{}",
+            self.seed,
+            self.score,
+            self.lines,
+            self.pieces,
+            self.get_time(),
+            clears.join(",")
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        let clears = CLEAR_TYPES
+            .iter()
+            .zip(self.clears)
+            .map(|(name, n)| (*name, Json::Number(n as f64)))
+            .collect();
+        Json::object(vec![
+            ("seed", Json::Number(self.seed as f64)),
+            ("score", Json::Number(self.score as f64)),
+            ("lines", Json::Number(self.lines as f64)),
+            ("pieces", Json::Number(self.pieces as f64)),
+            ("time", Json::Number(self.get_time())),
+            ("clears", Json::object(clears)),
+        ])
+    }
+}
+
+pub fn play(
+    player: &Player,
+    seed: u64,
+    max_pieces: u32,
+    mut on_event: impl FnMut(&Tetrus, &Event),
+) -> Result<Tetrus, String> {
+    let mut tetrus = Tetrus::new();
+    tetrus.set_seed(seed);
+    let mut controller = player.controller(seed)?;
+    let mut pieces = 0;
+    while !tetrus.is_game_over() && pieces < max_pieces {
+        controller.update(&mut tetrus);
+        while controller.is_waiting() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            controller.update(&mut tetrus);
+        }
+        tetrus.set_timing(Timing::from_tick(tetrus.get_tick()));
+        tetrus.update();
+        for event in tetrus.take_events() {
+            if event == Event::Lock {
+                pieces += 1;
+            }
+            on_event(&tetrus, &event);
+        }
+    }
+    Ok(tetrus)
+}
+
+pub fn simulate(player: &Player, seed: u64, max_pieces: u32) -> Result<GameStats, String> {
+    let mut stats = GameStats {
+        seed,
+        score: 0,
+        lines: 0,
+        pieces: 0,
+        frames: 0,
+        clears: [0; CLEAR_TYPES.len()],
+    };
+    let tetrus = play(player, seed, max_pieces, |_, event| match event {
+        Event::Lock => stats.pieces += 1,
+        Event::Clear(clear) => {
+            stats.lines += clear.lines;
+            stats.clears[clear_type(clear)] += 1;
+            if clear.perfect {
+                stats.clears[CLEAR_TYPES.len() - 1] += 1;
+            }
+        }
+        Event::Spawn | Event::Hold => (),
+    })?;
+    stats.score = tetrus.get_score();
+    stats.frames = tetrus.get_frame();
+    Ok(stats)
+}
@@ -5,6 +5,12 @@ pub struct SoundCollection {
     sounds: HashMap<String, Sound>,
 }
 
+impl Default for SoundCollection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SoundCollection {
     pub fn new() -> Self {
         SoundCollection {
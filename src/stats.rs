@@ -0,0 +1,229 @@
+use crate::constants::*;
+use crate::daily::*;
+use crate::report::*;
+use crate::save::*;
+use crate::tetrus::*;
+use crate::versus::*;
+
+#[derive(Clone, Default)]
+pub struct Stats {
+    pub games: u32,
+    pub time: f64,
+    pub pieces: u32,
+    pub keys: u32,
+    pub lines: u32,
+    // singles, doubles, triples and tetrises
+    pub clears: [u32; 4],
+    pub tspins: u32,
+    pub perfects: u32,
+    pub best_combo: u32,
+    pub attack: u32,
+}
+
+pub struct DayStats {
+    pub day: u64,
+    pub stats: Stats,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_key(&mut self) {
+        self.keys += 1;
+    }
+
+    pub fn on_events(&mut self, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::Lock => self.pieces += 1,
+                Event::Clear(clear) => {
+                    self.lines += clear.lines as u32;
+                    self.attack += get_attack(clear) as u32;
+                    self.clears[clear.lines.clamp(1, 4) - 1] += 1;
+                    if clear.tspin {
+                        self.tspins += 1;
+                    }
+                    if clear.perfect {
+                        self.perfects += 1;
+                    }
+                    self.best_combo = self.best_combo.max(clear.combo);
+                }
+                Event::Spawn | Event::Hold => (),
+            }
+        }
+    }
+
+    pub fn add(&mut self, other: &Stats) {
+        self.games += other.games;
+        self.time += other.time;
+        self.pieces += other.pieces;
+        self.keys += other.keys;
+        self.lines += other.lines;
+        for (total, count) in self.clears.iter_mut().zip(other.clears) {
+            *total += count;
+        }
+        self.tspins += other.tspins;
+        self.perfects += other.perfects;
+        self.best_combo = self.best_combo.max(other.best_combo);
+        self.attack += other.attack;
+    }
+
+    pub fn pps(&self) -> f64 {
+        if self.time > 0.0 {
+            self.pieces as f64 / self.time
+        } else {
+            0.0
+        }
+    }
+
+    pub fn apm(&self) -> f64 {
+        if self.time > 0.0 {
+            self.attack as f64 * 60.0 / self.time
+        } else {
+            0.0
+        }
+    }
+
+    pub fn kpp(&self) -> f64 {
+        if self.pieces > 0 {
+            self.keys as f64 / self.pieces as f64
+        } else {
+            0.0
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Stats> {
+        let mut fields: Vec<&str> = text.split_whitespace().collect();
+        // Lines saved before attack was tracked
+        if fields.len() == 12 {
+            fields.push("0");
+        }
+        let [games, time, pieces, keys, lines, single, double, triple, tetris, tspins, perfects, best_combo, attack] =
+            fields[..]
+        else {
+            return None;
+        };
+        Some(Stats {
+            games: games.parse().ok()?,
+            time: time.parse().ok()?,
+            pieces: pieces.parse().ok()?,
+            keys: keys.parse().ok()?,
+            lines: lines.parse().ok()?,
+            clears: [
+                single.parse().ok()?,
+                double.parse().ok()?,
+                triple.parse().ok()?,
+                tetris.parse().ok()?,
+            ],
+            tspins: tspins.parse().ok()?,
+            perfects: perfects.parse().ok()?,
+            best_combo: best_combo.parse().ok()?,
+            attack: attack.parse().ok()?,
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {:.1} {} {} {} {} {} {} {} {} {} {} {}",
+            self.games,
+            self.time,
+            self.pieces,
+            self.keys,
+            self.lines,
+            self.clears[0],
+            self.clears[1],
+            self.clears[2],
+            self.clears[3],
+            self.tspins,
+            self.perfects,
+            self.best_combo,
+            self.attack
+        )
+    }
+}
+
+fn parse_day(line: &str) -> Option<DayStats> {
+    let (day, stats) = line.split_once(' ')?;
+    Some(DayStats {
+        day: day.parse().ok()?,
+        stats: Stats::parse(stats)?,
+    })
+}
+
+// Lines that cannot be read are reported and the file is backed up before it is next rewritten.
+pub fn load_stats() -> Vec<DayStats> {
+    let lines = load_lines(STATS_SAVE);
+    let mut days = Vec::new();
+    let mut invalid = false;
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        match parse_day(line) {
+            Some(record) => days.push(record),
+            None => {
+                report(format!("{}: invalid line: {}", STATS_SAVE, line));
+                invalid = true;
+            }
+        }
+    }
+    if invalid {
+        save_lines(STATS_BACKUP, &lines);
+    }
+    days
+}
+
+pub fn save_stats(days: &[DayStats]) {
+    let lines: Vec<String> = days
+        .iter()
+        .map(|record| format!("{} {}", record.day, record.stats.to_line()))
+        .collect();
+    save_lines(STATS_SAVE, &lines);
+}
+
+// Adds a finished game to today's line and saves the file.
+pub fn record_game(days: &mut Vec<DayStats>, mut session: Stats, time: f64) {
+    session.games = 1;
+    session.time = time;
+    let day = today();
+    match days.iter_mut().find(|r| r.day == day) {
+        Some(record) => record.stats.add(&session),
+        None => days.push(DayStats {
+            day,
+            stats: session,
+        }),
+    }
+    save_stats(days);
+}
+
+pub fn lifetime(days: &[DayStats]) -> Stats {
+    let mut total = Stats::new();
+    for record in days {
+        total.add(&record.stats);
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trip() {
+        let line = "3 120.5 200 500 80 10 5 2 10 4 1 6 90";
+        assert_eq!(Stats::parse(line).unwrap().to_line(), line);
+    }
+
+    #[test]
+    fn parse_without_attack() {
+        let stats = Stats::parse("3 120.5 200 500 80 10 5 2 10 4 1 6").unwrap();
+        assert_eq!(stats.best_combo, 6);
+        assert_eq!(stats.attack, 0);
+    }
+
+    #[test]
+    fn parse_rejects_bad_lines() {
+        assert!(Stats::parse("3 120.5 200").is_none());
+        assert!(Stats::parse("3 120.5 200 500 80 10 5 2 10 4 1 x 90").is_none());
+        assert!(parse_day("day 3 120.5 200 500 80 10 5 2 10 4 1 6 90").is_none());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::constants::*;
+use crate::json::*;
+use crate::rng::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HolePattern {
+    Random,
+    Straight,
+    Zigzag,
+}
+
+impl HolePattern {
+    pub fn name(&self) -> &str {
+        match self {
+            HolePattern::Random => "Random",
+            HolePattern::Straight => "Straight",
+            HolePattern::Zigzag => "Zigzag",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<HolePattern> {
+        [
+            HolePattern::Random,
+            HolePattern::Straight,
+            HolePattern::Zigzag,
+        ]
+        .into_iter()
+        .find(|holes| holes.name() == name)
+    }
+
+    pub fn next(&self) -> HolePattern {
+        match self {
+            HolePattern::Random => HolePattern::Straight,
+            HolePattern::Straight => HolePattern::Zigzag,
+            HolePattern::Zigzag => HolePattern::Random,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SurvivalSettings {
+    pub interval: f64,
+    pub min_interval: f64,
+    pub acceleration: f64,
+    pub holes: HolePattern,
+}
+
+impl Default for SurvivalSettings {
+    fn default() -> Self {
+        SurvivalSettings {
+            interval: SURVIVAL_INTERVAL,
+            min_interval: SURVIVAL_MIN_INTERVAL,
+            acceleration: SURVIVAL_ACCELERATION,
+            holes: HolePattern::Random,
+        }
+    }
+}
+
+pub struct Survival {
+    settings: SurvivalSettings,
+    interval: f64,
+    timer: f64,
+    hole: usize,
+    step: i32,
+    rng: Rng,
+}
+
+impl Survival {
+    pub fn new(settings: SurvivalSettings, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        Survival {
+            interval: settings.interval,
+            settings,
+            timer: 0.0,
+            hole: rng.gen_range(0, GRID_WIDTH),
+            step: 1,
+            rng,
+        }
+    }
+
+    // Returns the hole column of the next garbage row once the timer runs out.
+    pub fn update(&mut self, dt: f64) -> Option<usize> {
+        self.timer += dt;
+        if self.timer < self.interval {
+            return None;
+        }
+        self.timer -= self.interval;
+        self.interval =
+            (self.interval * self.settings.acceleration).max(self.settings.min_interval);
+        Some(self.next_hole())
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("base_interval", Json::Number(self.settings.interval)),
+            ("min_interval", Json::Number(self.settings.min_interval)),
+            ("acceleration", Json::Number(self.settings.acceleration)),
+            ("holes", Json::string(self.settings.holes.name())),
+            ("interval", Json::Number(self.interval)),
+            ("timer", Json::Number(self.timer)),
+            ("hole", Json::Number(self.hole as f64)),
+            ("step", Json::Number(self.step as f64)),
+            ("rng", Json::String(self.rng.get_state().to_string())),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> Result<Survival, String> {
+        Ok(Survival {
+            settings: SurvivalSettings {
+                interval: json.get_f64("base_interval")?,
+                min_interval: json.get_f64("min_interval")?,
+                acceleration: json.get_f64("acceleration")?,
+                holes: HolePattern::parse(json.get_str("holes")?).ok_or("invalid holes")?,
+            },
+            interval: json.get_f64("interval")?,
+            timer: json.get_f64("timer")?,
+            hole: json.get_f64("hole")? as usize,
+            step: json.get_f64("step")? as i32,
+            rng: Rng::from_state(
+                json.get_str("rng")?
+                    .parse()
+                    .map_err(|_| String::from("invalid rng"))?,
+            ),
+        })
+    }
+
+    pub fn get_progress(&self) -> f64 {
+        self.timer / self.interval
+    }
+
+    fn next_hole(&mut self) -> usize {
+        match self.settings.holes {
+            HolePattern::Random => self.hole = self.rng.gen_range(0, GRID_WIDTH),
+            HolePattern::Straight => (),
+            HolePattern::Zigzag => {
+                if self.hole == 0 {
+                    self.step = 1;
+                } else if self.hole == GRID_WIDTH - 1 {
+                    self.step = -1;
+                }
+                self.hole = (self.hole as i32 + self.step) as usize;
+            }
+        }
+        self.hole
+    }
+}
@@ -0,0 +1,344 @@
+use crate::ai::*;
+use crate::constants::*;
+use crate::eval::*;
+use crate::json::*;
+use crate::report::*;
+use crate::tetrus::*;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+const TBP_BOARD_HEIGHT: usize = 40;
+
+fn offsets(id: &BlockType) -> [(i32, i32); 4] {
+    match id {
+        BlockType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        BlockType::J => [(-1, 1), (-1, 0), (0, 0), (1, 0)],
+        BlockType::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        BlockType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        BlockType::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        BlockType::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        BlockType::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+    }
+}
+
+fn location_cells(location: &Json) -> Option<(BlockType, Vec<(usize, usize)>)> {
+    let id = BlockType::from_char(location.get("type")?.as_str()?.chars().next()?)?;
+    let x = location.get("x")?.as_f64()? as i32;
+    let y = location.get("y")?.as_f64()? as i32;
+    let orientation = location.get("orientation")?.as_str()?;
+    let mut cells = Vec::new();
+    for (dx, dy) in offsets(&id) {
+        let (dx, dy) = match orientation {
+            "north" => (dx, dy),
+            "east" => (dy, -dx),
+            "south" => (-dx, -dy),
+            "west" => (-dy, dx),
+            _ => return None,
+        };
+        let (cx, cy) = (x + dx, y + dy);
+        if cx < 0 || cx >= GRID_WIDTH as i32 || cy < 0 || cy >= GRID_HEIGHT as i32 {
+            return None;
+        }
+        cells.push((cx as usize, GRID_HEIGHT - 1 - cy as usize));
+    }
+    cells.sort();
+    Some((id, cells))
+}
+
+fn board_cells(blocks: &[Block]) -> Vec<(usize, usize)> {
+    let mut cells: Vec<(usize, usize)> = blocks
+        .iter()
+        .map(|n| (n.position.x, n.position.y))
+        .collect();
+    cells.sort();
+    cells
+}
+
+fn piece_json(id: &BlockType) -> Json {
+    Json::String(id.to_char().to_string())
+}
+
+fn board_json(inactive: &[Block]) -> Json {
+    let mut rows = vec![vec![Json::Null; GRID_WIDTH]; TBP_BOARD_HEIGHT];
+    for block in inactive {
+        let cell = match BlockType::from_color(block.color) {
+            Some(id) => piece_json(&id),
+            None => Json::string("G"),
+        };
+        rows[GRID_HEIGHT - 1 - block.position.y][block.position.x] = cell;
+    }
+    Json::Array(rows.into_iter().map(Json::Array).collect())
+}
+
+// The bot opens with `info`, is sent `rules` and accepts them with `ready` or refuses with `error`.
+// Nothing else is sent before `ready`.
+#[derive(PartialEq)]
+enum Handshake {
+    Info,
+    Rules,
+    Ready,
+    Failed,
+}
+
+pub struct TbpBot {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    name: String,
+    exited: bool,
+    handshake: Handshake,
+    // Plays instead if the handshake fails
+    fallback: Option<Bot>,
+    // When the bot was last asked for something it has not answered yet
+    asked: Instant,
+    timeout: Duration,
+    piece: u32,
+    started: bool,
+    waiting: bool,
+    expected: Vec<(usize, usize)>,
+    plan: VecDeque<Movement>,
+}
+
+impl TbpBot {
+    pub fn new(command: &str) -> Result<Self, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("empty bot command")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {}", program, e))?;
+        let stdin = child.stdin.take().ok_or("bot has no stdin")?;
+        let stdout = child.stdout.take().ok_or("bot has no stdout")?;
+        let (sender, lines) = channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(TbpBot {
+            child,
+            stdin,
+            lines,
+            name: String::from(program),
+            exited: false,
+            handshake: Handshake::Info,
+            fallback: None,
+            asked: Instant::now(),
+            timeout: Duration::from_secs_f64(TBP_TIMEOUT),
+            piece: 0,
+            started: false,
+            waiting: false,
+            expected: Vec::new(),
+            plan: VecDeque::new(),
+        })
+    }
+
+    pub fn with_fallback(mut self, bot: Bot) -> Self {
+        self.fallback = Some(bot);
+        self
+    }
+
+    fn fail(&mut self, reason: &str) {
+        self.handshake = Handshake::Failed;
+        self.waiting = false;
+        match self.fallback {
+            Some(_) => report(format!("{}: {}, using the built-in bot", self.name, reason)),
+            None => report(format!("{}: {}", self.name, reason)),
+        }
+    }
+
+    fn send(&mut self, message: Json) {
+        if self.exited {
+            return;
+        }
+        if let Err(e) = writeln!(self.stdin, "{}", message) {
+            report(format!("{}: {}", self.name, e));
+        }
+    }
+
+    fn start(&mut self, tetrus: &Tetrus) {
+        if self.started {
+            self.send(Json::object(vec![("type", Json::string("stop"))]));
+        }
+        let queue = std::iter::once(tetrus.get_block_id())
+            .chain(tetrus.get_queue())
+            .map(piece_json)
+            .collect();
+        self.send(Json::object(vec![
+            ("type", Json::string("start")),
+            ("hold", tetrus.get_hold().map_or(Json::Null, piece_json)),
+            ("queue", Json::Array(queue)),
+            ("combo", Json::Number(tetrus.get_combo() as f64)),
+            ("back_to_back", Json::Bool(tetrus.is_b2b())),
+            ("board", board_json(&tetrus.inactive)),
+        ]));
+        self.started = true;
+    }
+
+    fn on_suggestion(&mut self, suggestion: &Json, tetrus: &Tetrus) {
+        let moves = suggestion.get("moves").and_then(|m| m.as_array());
+        let placements = placements(
+            &tetrus.active,
+            tetrus.get_origin(),
+            tetrus.get_block_id(),
+            &tetrus.inactive,
+        );
+        for suggested in moves.unwrap_or_default() {
+            let (id, cells) = match suggested.get("location").and_then(location_cells) {
+                Some(location) => location,
+                None => continue,
+            };
+            if id != *tetrus.get_block_id() {
+                continue;
+            }
+            let placement = placements.iter().find(|p| board_cells(&p.blocks) == cells);
+            if let Some(placement) = placement {
+                self.plan = placement.moves.clone().into();
+                self.expected = board_cells(&place(&tetrus.inactive, &placement.blocks).0);
+                self.send(Json::object(vec![
+                    ("type", Json::string("play")),
+                    ("move", suggested.clone()),
+                ]));
+                return;
+            }
+        }
+        report(format!("{}: no reachable suggestion, dropping", self.name));
+        self.plan = VecDeque::from([Movement::Drop]);
+        self.started = false;
+        self.send(Json::object(vec![("type", Json::string("stop"))]));
+    }
+}
+
+impl Controller for TbpBot {
+    fn update(&mut self, tetrus: &mut Tetrus) {
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        self.exited = true;
+                        self.waiting = false;
+                        match self.handshake {
+                            Handshake::Info | Handshake::Rules => self.fail("bot exited"),
+                            _ => report(format!("{}: bot exited", self.name)),
+                        }
+                    }
+                    break;
+                }
+            };
+            let message = match Json::parse(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    report(format!("{}: {}", self.name, e));
+                    continue;
+                }
+            };
+            match message.get("type").and_then(|t| t.as_str()) {
+                Some("info") if self.handshake == Handshake::Info => {
+                    if let Some(name) = message.get("name").and_then(|n| n.as_str()) {
+                        self.name = String::from(name);
+                    }
+                    self.send(Json::object(vec![("type", Json::string("rules"))]));
+                    self.handshake = Handshake::Rules;
+                    self.asked = Instant::now();
+                }
+                Some("ready") if self.handshake == Handshake::Rules => {
+                    self.handshake = Handshake::Ready;
+                }
+                Some("error") if self.handshake == Handshake::Rules => {
+                    let reason = message.get("reason").and_then(|r| r.as_str());
+                    self.fail(&format!(
+                        "rules rejected: {}",
+                        reason.unwrap_or("no reason")
+                    ));
+                    self.send(Json::object(vec![("type", Json::string("quit"))]));
+                }
+                Some("error") => report(format!("{}: {}", self.name, line)),
+                Some("suggestion") if self.waiting && tetrus.is_active() => {
+                    self.waiting = false;
+                    self.on_suggestion(&message, tetrus);
+                }
+                _ => (),
+            }
+        }
+
+        if self.is_waiting()
+            && self.handshake != Handshake::Failed
+            && self.asked.elapsed() > self.timeout
+        {
+            self.fail("bot timed out");
+            self.send(Json::object(vec![("type", Json::string("quit"))]));
+        }
+        if self.handshake == Handshake::Failed {
+            if let Some(bot) = &mut self.fallback {
+                bot.update(tetrus);
+            }
+            return;
+        }
+        if self.exited || self.handshake != Handshake::Ready || !tetrus.is_active() {
+            return;
+        }
+        if tetrus.get_pieces() != self.piece {
+            self.piece = tetrus.get_pieces();
+            self.plan.clear();
+            if !self.started || board_cells(&tetrus.inactive) != self.expected {
+                self.start(tetrus);
+            } else if let Some(id) = tetrus.get_queue().back() {
+                let piece = piece_json(id);
+                self.send(Json::object(vec![
+                    ("type", Json::string("new_piece")),
+                    ("piece", piece),
+                ]));
+            }
+            self.send(Json::object(vec![("type", Json::string("suggest"))]));
+            self.waiting = true;
+            self.asked = Instant::now();
+        }
+        while let Some(movement) = self.plan.pop_front() {
+            tetrus.player_move(movement);
+        }
+    }
+
+    fn is_waiting(&self) -> bool {
+        match self.handshake {
+            Handshake::Info | Handshake::Rules => !self.exited,
+            Handshake::Ready => self.waiting,
+            Handshake::Failed => self.fallback.as_ref().is_some_and(|bot| bot.is_waiting()),
+        }
+    }
+}
+
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        self.send(Json::object(vec![("type", Json::string("quit"))]));
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_bot_times_out_to_the_fallback() {
+        let fallback = Bot::new(Difficulty::Expert, 0);
+        let mut bot = TbpBot::new("sleep 10").unwrap().with_fallback(fallback);
+        bot.timeout = Duration::from_millis(50);
+        let mut tetrus = Tetrus::new();
+        bot.update(&mut tetrus);
+        assert!(bot.is_waiting());
+        std::thread::sleep(Duration::from_millis(100));
+        bot.update(&mut tetrus);
+        assert!(!bot.is_waiting());
+        assert!(bot.handshake == Handshake::Failed);
+    }
+}
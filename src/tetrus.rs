@@ -1,8 +1,12 @@
+use crate::board::*;
 use crate::constants::*;
-use crate::sounds::*;
+use crate::json::*;
+use crate::rng::*;
 use macroquad::prelude::Color;
-use macroquad::rand::gen_range;
+use macroquad::rand::rand;
+use std::collections::VecDeque;
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum Movement {
     Left,
     Right,
@@ -10,7 +14,7 @@ pub enum Movement {
     Rotate,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Collision {
     Left,
     Right,
@@ -28,6 +32,119 @@ pub enum BlockType {
     Z,
 }
 
+impl BlockType {
+    pub fn from_char(c: char) -> Option<BlockType> {
+        match c {
+            'I' => Some(BlockType::I),
+            'J' => Some(BlockType::J),
+            'L' => Some(BlockType::L),
+            'O' => Some(BlockType::O),
+            'S' => Some(BlockType::S),
+            'T' => Some(BlockType::T),
+            'Z' => Some(BlockType::Z),
+            _ => None,
+        }
+    }
+
+    pub fn from_color(color: Color) -> Option<BlockType> {
+        "IJLOSTZ"
+            .chars()
+            .filter_map(BlockType::from_char)
+            .find(|id| id.get_color() == color)
+    }
+
+    pub fn to_char(&self) -> char {
+        match self {
+            BlockType::I => 'I',
+            BlockType::J => 'J',
+            BlockType::L => 'L',
+            BlockType::O => 'O',
+            BlockType::S => 'S',
+            BlockType::T => 'T',
+            BlockType::Z => 'Z',
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.clone() as usize
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            BlockType::I => CYAN,
+            BlockType::J => PINK,
+            BlockType::L => ORANGE,
+            BlockType::O => YELLOW,
+            BlockType::S => RED,
+            BlockType::T => PURPLE,
+            BlockType::Z => GREEN,
+        }
+    }
+
+    pub fn get_blocks(&self) -> [(usize, usize); 4] {
+        match self {
+            BlockType::I => IBLOCK,
+            BlockType::J => JBLOCK,
+            BlockType::L => LBLOCK,
+            BlockType::O => OBLOCK,
+            BlockType::S => SBLOCK,
+            BlockType::T => TBLOCK,
+            BlockType::Z => ZBLOCK,
+        }
+    }
+
+    pub fn get_origin(&self) -> (usize, usize) {
+        match self {
+            BlockType::I => IORIGIN,
+            BlockType::J => JORIGIN,
+            BlockType::L => LORIGIN,
+            BlockType::O => OORIGIN,
+            BlockType::S => SORIGIN,
+            BlockType::T => TORIGIN,
+            BlockType::Z => ZORIGIN,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct LineClear {
+    pub lines: usize,
+    pub perfect: bool,
+    pub tspin: bool,
+    pub combo: u32,
+    pub b2b: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Event {
+    Spawn,
+    Hold,
+    Lock,
+    Clear(LineClear),
+}
+
+#[derive(Clone, Copy)]
+pub struct Timing {
+    pub gravity: u32,
+    pub soft_gravity: u32,
+    pub are: u32,
+    pub lock_delay: u32,
+    pub clear_delay: u32,
+}
+
+impl Timing {
+    pub fn from_tick(tick: f64) -> Self {
+        let frames = (tick * FRAME_RATE).round() as u32;
+        Timing {
+            gravity: GRAVITY_UNIT / frames,
+            soft_gravity: GRAVITY_UNIT / (SOFT_DROP_TICK * FRAME_RATE) as u32,
+            are: frames,
+            lock_delay: frames,
+            clear_delay: 0,
+        }
+    }
+}
+
 #[derive(Default, Clone, PartialEq)]
 pub struct Position {
     pub x: usize,
@@ -35,7 +152,7 @@ pub struct Position {
 }
 
 impl Position {
-    fn new(pos: (usize, usize)) -> Self {
+    pub fn new(pos: (usize, usize)) -> Self {
         Position { x: pos.0, y: pos.1 }
     }
 }
@@ -44,6 +161,7 @@ impl Position {
 pub struct Block {
     pub position: Position,
     pub color: Color,
+    pub locked_at: u64,
 }
 
 impl Default for Block {
@@ -51,78 +169,486 @@ impl Default for Block {
         Block {
             position: Position::default(),
             color: BLACK,
+            locked_at: 0,
+        }
+    }
+}
+
+pub fn rotate(active: &[Block], origin: &Position, inactive: &[Block]) -> Option<Vec<Block>> {
+    let mut active_rotated: Vec<Block> = Vec::new();
+    for block in active {
+        let offset_x: i32 = origin.x as i32 - block.position.x as i32;
+        let offset_y: i32 = origin.y as i32 - block.position.y as i32;
+        let x1 = offset_y;
+        let y1 = -offset_x;
+
+        let x = origin.x as i32 + x1;
+        let y = origin.y as i32 + y1;
+
+        active_rotated.push(Block {
+            position: Position::new((x as usize, y as usize)),
+            color: block.color,
+            locked_at: 0,
+        })
+    }
+    for temp in &active_rotated {
+        if temp.position.x >= GRID_WIDTH || temp.position.y >= GRID_HEIGHT {
+            return None;
+        }
+        for block in inactive {
+            if block.position == temp.position {
+                return None;
+            }
         }
     }
+    Some(active_rotated)
 }
 
+pub fn collides(active: &[Block], inactive: &[Block], collision: Collision) -> bool {
+    match collision {
+        Collision::Left | Collision::Right => {
+            for block in active {
+                if (block.position.x == 0 && collision == Collision::Left)
+                    || (block.position.x == 9 && collision == Collision::Right)
+                {
+                    return true;
+                }
+                for col_block in inactive {
+                    if collision == Collision::Left {
+                        if block.position
+                            == Position::new((col_block.position.x + 1, col_block.position.y))
+                        {
+                            return true;
+                        }
+                    } else if block.position
+                        == Position::new((
+                            col_block.position.x.saturating_sub(1),
+                            col_block.position.y,
+                        ))
+                    {
+                        return true;
+                    }
+                }
+            }
+            false
+        }
+        Collision::Down => {
+            for block in active {
+                if block.position.y >= 23 {
+                    return true;
+                }
+                for col_block in inactive {
+                    if block.position.x == col_block.position.x
+                        && col_block.position.y.checked_sub(1) == Some(block.position.y)
+                    {
+                        return true;
+                    }
+                }
+            }
+            false
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Tetrus {
     pub active: Vec<Block>,
     pub inactive: Vec<Block>,
-    pub sounds: SoundCollection,
     origin: Position,
     block_id: BlockType,
     tick: f64,
-    fast_tick: f64,
     score: u32,
+    timing: Timing,
+    soft_drop: bool,
+    gravity: u32,
+    lock_timer: u32,
+    are_timer: u32,
+    clear_timer: u32,
+    clearing: Vec<usize>,
+    events: Vec<Event>,
+    frame: u64,
+    queue: VecDeque<BlockType>,
+    hold: Option<BlockType>,
+    // Hold can be used once per piece
+    held: bool,
+    scripted: bool,
+    rotated: bool,
+    rng: Rng,
+    combo: u32,
+    b2b: bool,
+    pieces: u32,
+    last_piece: Vec<Block>,
+    last_board: Vec<Block>,
 }
 
-impl Tetrus {
-    pub async fn new() -> Self {
-        let mut sounds = SoundCollection::new();
-        #[cfg(target_arch = "wasm32")]
-        {
-            sounds.add_sound("/audio/tetrus_drop.wav", "drop").await;
-            sounds.add_sound("/audio/tetrus_rotate.wav", "rotate").await;
-            sounds.add_sound("/audio/tetrus_set.wav", "set").await;
-        }
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            sounds.add_sound("audio/tetrus_drop.wav", "drop").await;
-            sounds.add_sound("audio/tetrus_rotate.wav", "rotate").await;
-            sounds.add_sound("audio/tetrus_set.wav", "set").await;
-        }
+impl Default for Tetrus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl Tetrus {
+    pub fn new() -> Self {
         Tetrus {
             active: Vec::new(),
             inactive: Vec::new(),
-            sounds,
             origin: Position::new((0, 0)),
             block_id: BlockType::I,
             tick: 0.4,
-            fast_tick: 0.1,
             score: 0,
+            timing: Timing::from_tick(0.4),
+            soft_drop: false,
+            gravity: 0,
+            lock_timer: 0,
+            are_timer: 0,
+            clear_timer: 0,
+            clearing: Vec::new(),
+            events: Vec::new(),
+            frame: 0,
+            queue: VecDeque::new(),
+            hold: None,
+            held: false,
+            scripted: false,
+            rotated: false,
+            rng: Rng::new(rand() as u64),
+            combo: 0,
+            b2b: false,
+            pieces: 0,
+            last_piece: Vec::new(),
+            last_board: Vec::new(),
         }
     }
 
-    fn create_block(&mut self, color: Color, blocks: [(usize, usize); 4], id: BlockType) {
-        for block in blocks {
+    fn create_block(&mut self, id: BlockType) {
+        for block in id.get_blocks() {
             let position = Position::new(block);
-            self.active.push(Block { position, color })
+            self.active.push(Block {
+                position,
+                color: id.get_color(),
+                locked_at: 0,
+            })
         }
-        self.block_id = id.clone();
-        match id {
-            BlockType::I => self.origin = Position::new(IORIGIN),
-            BlockType::J => self.origin = Position::new(JORIGIN),
-            BlockType::L => self.origin = Position::new(LORIGIN),
-            BlockType::O => self.origin = Position::new(OORIGIN),
-            BlockType::S => self.origin = Position::new(SORIGIN),
-            BlockType::T => self.origin = Position::new(TORIGIN),
-            BlockType::Z => self.origin = Position::new(ZORIGIN),
+        self.origin = Position::new(id.get_origin());
+        self.block_id = id;
+        self.rotated = false;
+    }
+
+    fn next_piece(&mut self) -> Option<BlockType> {
+        while !self.scripted && self.queue.len() <= PREVIEW_COUNT {
+            let id = match self.rng.gen_range(0, 7) {
+                0 => BlockType::I,
+                1 => BlockType::J,
+                2 => BlockType::L,
+                3 => BlockType::O,
+                4 => BlockType::S,
+                5 => BlockType::T,
+                6 => BlockType::Z,
+                _ => panic!("Invalid range generated: tetrus.spawn_block()"),
+            };
+            self.queue.push_back(id);
         }
+        self.queue.pop_front()
     }
 
     pub fn spawn_block(&mut self) {
-        match gen_range(0, 7) {
-            0 => self.create_block(CYAN, IBLOCK, BlockType::I),
-            1 => self.create_block(PINK, JBLOCK, BlockType::J),
-            2 => self.create_block(ORANGE, LBLOCK, BlockType::L),
-            3 => self.create_block(YELLOW, OBLOCK, BlockType::O),
-            4 => self.create_block(RED, SBLOCK, BlockType::S),
-            5 => self.create_block(PURPLE, TBLOCK, BlockType::T),
-            6 => self.create_block(GREEN, ZBLOCK, BlockType::Z),
-            _ => panic!("Invalid range generated: tetrus.spawn_block()"),
-        }
+        let id = match self.next_piece() {
+            Some(id) => id,
+            None => return,
+        };
+        self.create_block(id);
+        self.held = false;
+        self.pieces += 1;
         self.update_score(5);
+        self.events.push(Event::Spawn);
+    }
+
+    // Swaps the active piece with the held one, or with the next piece if the hold is empty. Only
+    // once per piece, and the new piece starts again from the top.
+    pub fn hold_piece(&mut self) {
+        if self.held || !self.is_active() {
+            return;
+        }
+        let id = match self.hold.take() {
+            Some(id) => id,
+            None => match self.next_piece() {
+                Some(id) => id,
+                None => return,
+            },
+        };
+        self.hold = Some(self.block_id.clone());
+        self.active.clear();
+        self.create_block(id);
+        self.held = true;
+        self.gravity = 0;
+        self.lock_timer = 0;
+        self.events.push(Event::Hold);
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn set_board(&mut self, blocks: Vec<Block>) {
+        self.inactive = blocks;
+    }
+
+    pub fn set_active(&mut self, blocks: Vec<Block>) -> Result<(), String> {
+        let mut cells: Vec<(i32, i32)> = blocks
+            .iter()
+            .map(|n| (n.position.x as i32, n.position.y as i32))
+            .collect();
+        cells.sort();
+        if cells.len() != 4 {
+            return Err(String::from("active piece must be 4 cells"));
+        }
+        for id in "IJLOSTZ".chars().filter_map(BlockType::from_char) {
+            let (ox, oy) = id.get_origin();
+            let mut shape: Vec<(i32, i32)> = id
+                .get_blocks()
+                .iter()
+                .map(|(x, y)| (*x as i32 - ox as i32, *y as i32 - oy as i32))
+                .collect();
+            for _ in 0..4 {
+                for (dx, dy) in &shape {
+                    let origin = (cells[0].0 - dx, cells[0].1 - dy);
+                    let mut placed: Vec<(i32, i32)> = shape
+                        .iter()
+                        .map(|(x, y)| (origin.0 + x, origin.1 + y))
+                        .collect();
+                    placed.sort();
+                    if placed != cells || origin.0 < 0 || origin.1 < 0 {
+                        continue;
+                    }
+                    self.active = blocks
+                        .iter()
+                        .map(|n| Block {
+                            color: id.get_color(),
+                            ..n.clone()
+                        })
+                        .collect();
+                    self.origin = Position::new((origin.0 as usize, origin.1 as usize));
+                    self.block_id = id;
+                    self.rotated = false;
+                    return Ok(());
+                }
+                shape = shape.iter().map(|(x, y)| (-y, *x)).collect();
+            }
+        }
+        Err(String::from("active cells are not a tetromino"))
+    }
+
+    pub fn from_text(text: &str) -> Result<Tetrus, String> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        let (board, active) = parse_rows(&rows)?;
+        let mut tetrus = Tetrus::new();
+        tetrus.set_board(board);
+        if !active.is_empty() {
+            tetrus.set_active(active)?;
+        }
+        Ok(tetrus)
+    }
+
+    pub fn to_text(&self) -> String {
+        format_board(&self.inactive, &self.active).join("\n")
+    }
+
+    pub fn to_json(&self) -> Json {
+        let number = |n: f64| Json::Number(n);
+        Json::object(vec![
+            (
+                "board",
+                Json::Array(
+                    format_board(&self.inactive, &self.active)
+                        .iter()
+                        .map(|row| Json::string(row))
+                        .collect(),
+                ),
+            ),
+            ("piece", Json::String(self.block_id.to_char().to_string())),
+            (
+                "origin",
+                Json::Array(vec![
+                    number(self.origin.x as f64),
+                    number(self.origin.y as f64),
+                ]),
+            ),
+            ("rotated", Json::Bool(self.rotated)),
+            (
+                "queue",
+                Json::String(self.queue.iter().map(|id| id.to_char()).collect()),
+            ),
+            (
+                "hold",
+                Json::String(self.hold.iter().map(|id| id.to_char()).collect()),
+            ),
+            ("held", Json::Bool(self.held)),
+            ("scripted", Json::Bool(self.scripted)),
+            ("rng", Json::String(self.rng.get_state().to_string())),
+            ("tick", number(self.tick)),
+            ("score", number(self.score as f64)),
+            ("gravity", number(self.gravity as f64)),
+            ("lock_timer", number(self.lock_timer as f64)),
+            ("are_timer", number(self.are_timer as f64)),
+            ("clear_timer", number(self.clear_timer as f64)),
+            (
+                "clearing",
+                Json::Array(self.clearing.iter().map(|y| number(*y as f64)).collect()),
+            ),
+            ("frame", number(self.frame as f64)),
+            ("combo", number(self.combo as f64)),
+            ("b2b", Json::Bool(self.b2b)),
+            ("pieces", number(self.pieces as f64)),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> Result<Tetrus, String> {
+        let rows: Vec<&str> = json
+            .get("board")
+            .and_then(Json::as_array)
+            .ok_or("missing board")?
+            .iter()
+            .filter_map(Json::as_str)
+            .collect();
+        let (mut inactive, active) = parse_rows(&rows)?;
+        let id = json
+            .get_str("piece")?
+            .chars()
+            .next()
+            .and_then(BlockType::from_char)
+            .ok_or("invalid piece")?;
+        let origin: Vec<usize> = json
+            .get("origin")
+            .and_then(Json::as_array)
+            .ok_or("missing origin")?
+            .iter()
+            .filter_map(|n| n.as_f64().map(|n| n as usize))
+            .collect();
+        let queue = json
+            .get_str("queue")?
+            .chars()
+            .map(|c| BlockType::from_char(c).ok_or(format!("invalid piece in queue: {}", c)))
+            .collect::<Result<_, _>>()?;
+        // Games saved before hold was added have neither field
+        let hold = match json.get("hold").and_then(Json::as_str) {
+            Some(hold) => hold
+                .chars()
+                .next()
+                .map(|c| BlockType::from_char(c).ok_or(format!("invalid hold piece: {}", c))),
+            None => None,
+        }
+        .transpose()?;
+        let clearing = json
+            .get("clearing")
+            .and_then(Json::as_array)
+            .ok_or("missing clearing")?
+            .iter()
+            .filter_map(|n| n.as_f64().map(|n| n as usize))
+            .collect();
+        let rng = json
+            .get_str("rng")?
+            .parse()
+            .map_err(|_| String::from("invalid rng"))?;
+        let frame = json.get_f64("frame")? as u64;
+        for block in &mut inactive {
+            block.locked_at = frame;
+        }
+
+        let mut tetrus = Tetrus::new();
+        tetrus.inactive = inactive;
+        tetrus.active = active
+            .into_iter()
+            .map(|n| Block {
+                color: id.get_color(),
+                ..n
+            })
+            .collect();
+        tetrus.origin = match origin[..] {
+            [x, y] => Position::new((x, y)),
+            _ => return Err(String::from("invalid origin")),
+        };
+        tetrus.block_id = id;
+        tetrus.rotated = json.get_bool("rotated")?;
+        tetrus.queue = queue;
+        tetrus.hold = hold;
+        tetrus.held = json.get("held").and_then(Json::as_bool).unwrap_or(false);
+        tetrus.scripted = json.get_bool("scripted")?;
+        tetrus.rng = Rng::from_state(rng);
+        tetrus.tick = json.get_f64("tick")?;
+        tetrus.timing = Timing::from_tick(tetrus.tick);
+        tetrus.score = json.get_f64("score")? as u32;
+        tetrus.gravity = json.get_f64("gravity")? as u32;
+        tetrus.lock_timer = json.get_f64("lock_timer")? as u32;
+        tetrus.are_timer = json.get_f64("are_timer")? as u32;
+        tetrus.clear_timer = json.get_f64("clear_timer")? as u32;
+        tetrus.clearing = clearing;
+        tetrus.frame = frame;
+        tetrus.combo = json.get_f64("combo")? as u32;
+        tetrus.b2b = json.get_bool("b2b")?;
+        tetrus.pieces = json.get_f64("pieces")? as u32;
+        Ok(tetrus)
+    }
+
+    pub fn set_queue(&mut self, queue: Vec<BlockType>) {
+        self.queue = queue.into();
+        self.scripted = true;
+    }
+
+    pub fn set_upcoming(&mut self, queue: Vec<BlockType>) {
+        self.queue = queue.into();
+        self.scripted = false;
+    }
+
+    pub fn get_queue(&self) -> &VecDeque<BlockType> {
+        &self.queue
+    }
+
+    pub fn set_hold(&mut self, hold: Option<BlockType>) {
+        self.hold = hold;
+    }
+
+    pub fn get_hold(&self) -> Option<&BlockType> {
+        self.hold.as_ref()
+    }
+
+    pub fn is_hold_used(&self) -> bool {
+        self.held
+    }
+
+    pub fn get_origin(&self) -> &Position {
+        &self.origin
+    }
+
+    pub fn get_block_id(&self) -> &BlockType {
+        &self.block_id
+    }
+
+    pub fn get_pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    pub fn get_combo(&self) -> u32 {
+        self.combo
+    }
+
+    pub fn is_b2b(&self) -> bool {
+        self.b2b
+    }
+
+    pub fn get_last_piece(&self) -> &[Block] {
+        &self.last_piece
+    }
+
+    pub fn get_last_board(&self) -> &[Block] {
+        &self.last_board
+    }
+
+    // Rows waiting out the clear delay
+    pub fn get_clearing(&self) -> &[usize] {
+        &self.clearing
     }
 
     pub fn is_active(&self) -> bool {
@@ -130,13 +656,26 @@ impl Tetrus {
     }
 
     fn change_status(&mut self) {
-        while !self.active.is_empty() {
-            self.inactive.push(self.active.pop().unwrap());
+        while let Some(mut block) = self.active.pop() {
+            block.locked_at = self.frame;
+            self.inactive.push(block);
         }
     }
 
-    pub fn swap_tick(&mut self) {
-        std::mem::swap(&mut self.tick, &mut self.fast_tick);
+    pub fn set_soft_drop(&mut self, soft_drop: bool) {
+        self.soft_drop = soft_drop;
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
     }
 
     pub fn get_tick(&self) -> f64 {
@@ -151,123 +690,151 @@ impl Tetrus {
         self.score
     }
 
+    pub fn get_level(&self) -> u32 {
+        ((0.4 - self.tick) / 0.01).round() as u32 + 1
+    }
+
     fn update_tick(&mut self) {
         if self.tick >= 0.1 {
             self.tick -= 0.01;
         }
     }
 
-    pub fn update_active(&mut self) {
-        if !self.check_collision(Collision::Down) {
-            self.move_active();
+    pub fn update(&mut self) {
+        self.frame += 1;
+        if self.clear_timer > 0 {
+            self.clear_timer -= 1;
+            if self.clear_timer == 0 {
+                self.collapse();
+            }
+            return;
+        }
+        if !self.is_active() {
+            if self.are_timer > 0 {
+                self.are_timer -= 1;
+                return;
+            }
+            self.spawn_block();
+        }
+
+        let gravity = if self.soft_drop {
+            self.timing.gravity.max(self.timing.soft_gravity)
         } else {
-            self.change_status();
-            while self.check_clear() {
-                self.sounds.play("set", SOUND_PARAMS);
-                self.update_tick();
-                self.update_score(100);
+            self.timing.gravity
+        };
+        self.gravity += gravity;
+        while self.gravity >= GRAVITY_UNIT {
+            self.gravity -= GRAVITY_UNIT;
+            if self.check_collision(Collision::Down) {
+                self.gravity = 0;
+                break;
+            }
+            self.move_active();
+            self.lock_timer = 0;
+        }
+
+        if self.check_collision(Collision::Down) {
+            self.lock_timer += 1;
+            if self.soft_drop || self.lock_timer >= self.timing.lock_delay {
+                self.lock();
             }
         }
     }
 
+    fn lock(&mut self) {
+        let tspin = self.is_tspin();
+        self.last_piece = self.active.clone();
+        self.last_board = self.inactive.clone();
+        self.change_status();
+        self.gravity = 0;
+        self.lock_timer = 0;
+        self.events.push(Event::Lock);
+
+        self.clearing = self.full_rows();
+        if self.clearing.is_empty() {
+            self.combo = 0;
+            self.are_timer = self.timing.are;
+            return;
+        }
+        let lines = self.clearing.len();
+        let difficult = lines == 4 || tspin;
+        self.events.push(Event::Clear(LineClear {
+            lines,
+            perfect: self.inactive.len() == lines * GRID_WIDTH,
+            tspin,
+            combo: self.combo,
+            b2b: difficult && self.b2b,
+        }));
+        self.combo += 1;
+        self.b2b = difficult;
+        for _ in 0..lines {
+            self.update_tick();
+            self.update_score(100);
+        }
+        self.clear_timer = self.timing.clear_delay;
+        if self.clear_timer == 0 {
+            self.collapse();
+        }
+    }
+
     fn move_active(&mut self) {
-        for mut block in &mut self.active {
+        for block in &mut self.active {
             block.position.y += 1;
         }
         self.origin.y += 1;
+        self.rotated = false;
     }
 
-    fn rotate_block(&mut self) {
-        let mut active_rotated: Vec<Block> = Vec::new();
-        for block in &mut self.active {
-            let offset_x: i32 = self.origin.x as i32 - block.position.x as i32;
-            let offset_y: i32 = self.origin.y as i32 - block.position.y as i32;
-            let x1 = offset_y;
-            let y1 = -offset_x;
-
-            let x = self.origin.x as i32 + x1;
-            let y = self.origin.y as i32 + y1;
-
-            active_rotated.push(Block {
-                position: Position::new((x as usize, y as usize)),
-                color: block.color,
-            })
-        }
-        for temp in &active_rotated {
-            if temp.position.x >= GRID_WIDTH || temp.position.y >= GRID_HEIGHT {
-                return;
-            }
-            for block in &self.inactive {
-                if block.position == temp.position {
-                    return;
-                }
-            }
+    fn is_tspin(&self) -> bool {
+        if self.block_id != BlockType::T || !self.rotated {
+            return false;
         }
-        self.active = active_rotated;
+        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .iter()
+            .filter(|(dx, dy)| {
+                let x = self.origin.x as i32 + dx;
+                let y = self.origin.y as i32 + dy;
+                x < 0
+                    || x >= GRID_WIDTH as i32
+                    || y >= GRID_HEIGHT as i32
+                    || self
+                        .inactive
+                        .iter()
+                        .any(|n| n.position == Position::new((x as usize, y as usize)))
+            })
+            .count();
+        corners >= 3
     }
 
-    fn check_collision(&mut self, collision: Collision) -> bool {
-        match collision {
-            Collision::Left | Collision::Right => {
-                for block in &self.active {
-                    if (block.position.x == 0 && collision == Collision::Left)
-                        || (block.position.x == 9 && collision == Collision::Right)
-                    {
-                        return true;
-                    }
-                    for col_block in &self.inactive {
-                        if collision == Collision::Left {
-                            if block.position
-                                == Position::new((col_block.position.x + 1, col_block.position.y))
-                            {
-                                return true;
-                            }
-                        } else if block.position
-                            == Position::new((
-                                col_block.position.x.saturating_sub(1),
-                                col_block.position.y,
-                            ))
-                        {
-                            return true;
-                        }
-                    }
-                }
-                false
-            }
-            Collision::Down => {
-                for block in &self.active {
-                    if block.position.y >= 23 {
-                        return true;
-                    }
-                    for col_block in &self.inactive {
-                        if block.position
-                            == Position::new((col_block.position.x, col_block.position.y - 1))
-                        {
-                            return true;
-                        }
-                    }
-                }
-                false
-            }
+    fn rotate_block(&mut self) {
+        if let Some(active) = rotate(&self.active, &self.origin, &self.inactive) {
+            self.active = active;
+            self.rotated = true;
         }
     }
 
+    fn check_collision(&self, collision: Collision) -> bool {
+        collides(&self.active, &self.inactive, collision)
+    }
+
     pub fn player_move(&mut self, direction: Movement) {
         match direction {
             Movement::Left => {
                 if !self.check_collision(Collision::Left) {
-                    for mut block in &mut self.active {
+                    for block in &mut self.active {
                         block.position.x -= 1;
                     }
                     self.origin.x -= 1;
+                    self.rotated = false;
                 }
             }
             Movement::Right => {
                 if !self.check_collision(Collision::Right) {
-                    for mut block in &mut self.active {
+                    for block in &mut self.active {
                         block.position.x += 1;
                     }
                     self.origin.x += 1;
+                    self.rotated = false;
                 }
             }
             Movement::Drop => {
@@ -283,26 +850,46 @@ impl Tetrus {
         }
     }
 
-    fn check_clear(&mut self) -> bool {
-        for i in 4..GRID_HEIGHT {
-            let count = self.inactive.iter().filter(|n| n.position.y == i).count();
-            if count == GRID_WIDTH {
-                let mut temp_inactive: Vec<Block> = self
-                    .inactive
-                    .iter()
-                    .cloned()
-                    .filter(|n| n.position.y != i)
-                    .collect();
-                for val in &mut temp_inactive {
-                    if val.position.y < i {
-                        val.position.y += 1;
-                    }
-                }
-                self.inactive = temp_inactive;
-                return true;
-            }
+    fn full_rows(&self) -> Vec<usize> {
+        (4..GRID_HEIGHT)
+            .filter(|i| self.inactive.iter().filter(|n| n.position.y == *i).count() == GRID_WIDTH)
+            .collect()
+    }
+
+    fn collapse(&mut self) {
+        let clearing = std::mem::take(&mut self.clearing);
+        self.inactive.retain(|n| !clearing.contains(&n.position.y));
+        for block in &mut self.inactive {
+            block.position.y += clearing.iter().filter(|i| **i > block.position.y).count();
         }
-        false
+        self.are_timer = self.timing.are;
+    }
+
+    // Returns true when the stack tops out: blocks pushed off the top are removed.
+    pub fn raise_garbage(&mut self, hole: usize) -> bool {
+        let blocks = self.inactive.len();
+        self.inactive.retain(|n| n.position.y > 0);
+        let pushed_out = self.inactive.len() < blocks;
+        for block in &mut self.inactive {
+            block.position.y -= 1;
+        }
+        self.clearing = self
+            .clearing
+            .iter()
+            .filter_map(|row| row.checked_sub(1))
+            .collect();
+        for x in (0..GRID_WIDTH).filter(|x| *x != hole) {
+            self.inactive.push(Block {
+                position: Position::new((x, GRID_HEIGHT - 1)),
+                color: GREY,
+                locked_at: self.frame,
+            });
+        }
+        pushed_out
+            || self
+                .active
+                .iter()
+                .any(|block| self.inactive.iter().any(|n| n.position == block.position))
     }
 
     pub fn is_game_over(&self) -> bool {
@@ -314,3 +901,126 @@ impl Tetrus {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(cells: &[(usize, usize)]) -> Vec<Block> {
+        cells
+            .iter()
+            .map(|&cell| Block {
+                position: Position::new(cell),
+                color: GREY,
+                locked_at: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hold_swaps_once_per_piece() {
+        let mut tetrus = Tetrus::new();
+        tetrus.set_queue(vec![BlockType::T, BlockType::I, BlockType::O]);
+        tetrus.update();
+        tetrus.hold_piece();
+        assert!(*tetrus.get_block_id() == BlockType::I);
+        assert!(tetrus.get_hold() == Some(&BlockType::T));
+        assert_eq!(tetrus.get_queue().len(), 1);
+        tetrus.hold_piece();
+        assert!(*tetrus.get_block_id() == BlockType::I);
+
+        tetrus.take_events();
+        tetrus.player_move(Movement::Drop);
+        while !tetrus.take_events().contains(&Event::Spawn) {
+            tetrus.update();
+        }
+        assert!(*tetrus.get_block_id() == BlockType::O);
+        tetrus.hold_piece();
+        assert!(*tetrus.get_block_id() == BlockType::T);
+        assert!(tetrus.get_hold() == Some(&BlockType::O));
+        assert_eq!(tetrus.get_pieces(), 2);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        for text in [
+            "IIII..ZZ..\nGGGGG.GGGG",
+            "....@.....\n...@@@....\nIIII..ZZ..\nGGGGG.GGGG",
+            "@@@@......\n..........\nJ...LL..OO",
+        ] {
+            assert_eq!(Tetrus::from_text(text).unwrap().to_text(), text);
+        }
+    }
+
+    #[test]
+    fn text_finds_rotated_pieces() {
+        for (text, id) in [
+            ("@@@@......", BlockType::I),
+            (".@@.......\n@@........", BlockType::S),
+            ("@@........\n.@@.......", BlockType::Z),
+            ("@.........\n@@@.......", BlockType::J),
+            ("@@@.......\n..@.......", BlockType::J),
+            ("..@.......\n@@@.......", BlockType::L),
+            ("@@........\n.@........\n.@........", BlockType::L),
+            ("@.........\n@@........\n@.........", BlockType::T),
+        ] {
+            let tetrus = Tetrus::from_text(text).unwrap();
+            assert!(*tetrus.get_block_id() == id, "{}", text);
+            assert_eq!(tetrus.to_text(), text);
+        }
+    }
+
+    #[test]
+    fn set_active_checks_the_shape() {
+        let mut tetrus = Tetrus::new();
+        assert!(tetrus
+            .set_active(cells(&[(3, 10), (3, 11), (4, 11), (4, 12)]))
+            .is_ok());
+        assert!(*tetrus.get_block_id() == BlockType::S);
+        assert!(tetrus
+            .set_active(cells(&[(3, 10), (4, 11), (4, 10), (3, 11)]))
+            .is_ok());
+        assert!(*tetrus.get_block_id() == BlockType::O);
+
+        let error = Err(String::from("active piece must be 4 cells"));
+        assert_eq!(
+            tetrus.set_active(cells(&[(3, 10), (4, 10), (5, 10)])),
+            error
+        );
+        assert_eq!(
+            tetrus.set_active(cells(&[(3, 10), (4, 10), (5, 10), (6, 10), (7, 10)])),
+            error
+        );
+        assert_eq!(
+            tetrus.set_active(cells(&[(3, 10), (4, 10), (5, 11), (6, 11)])),
+            Err(String::from("active cells are not a tetromino"))
+        );
+        assert_eq!(
+            Tetrus::from_text("@@........\n..@@......").err(),
+            Some(String::from("active cells are not a tetromino"))
+        );
+    }
+    #[test]
+    fn raise_garbage_pushes_out_the_top_row() {
+        let mut tetrus = Tetrus::from_text(&"GGGGGGGGG.\n".repeat(GRID_HEIGHT)).unwrap();
+        tetrus.clearing = vec![0, GRID_HEIGHT - 1];
+        assert!(tetrus.raise_garbage(0));
+        assert_eq!(tetrus.inactive.len(), GRID_HEIGHT * (GRID_WIDTH - 1));
+        let mut cells: Vec<(usize, usize)> = tetrus
+            .inactive
+            .iter()
+            .map(|n| (n.position.x, n.position.y))
+            .collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), tetrus.inactive.len());
+        assert_eq!(tetrus.clearing, [GRID_HEIGHT - 2]);
+    }
+
+    #[test]
+    fn raise_garbage_below_the_top() {
+        let mut tetrus = Tetrus::from_text("GGGGGGGGG.").unwrap();
+        assert!(!tetrus.raise_garbage(9));
+        assert_eq!(tetrus.to_text(), "GGGGGGGGG.\nGGGGGGGGG.");
+    }
+}
@@ -0,0 +1,476 @@
+use crate::constants::*;
+use crate::tetrus::*;
+use macroquad::file::load_string;
+use macroquad::prelude::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GridStyle {
+    Lines,
+    Dots,
+    Hidden,
+}
+
+impl GridStyle {
+    fn parse(name: &str) -> Option<GridStyle> {
+        match name {
+            "lines" => Some(GridStyle::Lines),
+            "dots" => Some(GridStyle::Dots),
+            "hidden" => Some(GridStyle::Hidden),
+            _ => None,
+        }
+    }
+}
+
+// Piece colors for color vision deficiencies. `Theme` keeps the theme's own colors.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Palette {
+    Theme,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Theme,
+        Palette::Protanopia,
+        Palette::Deuteranopia,
+        Palette::Tritanopia,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Palette::Theme => "Theme",
+            Palette::Protanopia => "Protanopia",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Tritanopia => "Tritanopia",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Palette> {
+        Palette::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    // IJLOSTZ colors chosen to differ in lightness as well as hue under each deficiency.
+    fn colors(&self) -> Option<[&str; 7]> {
+        match self {
+            Palette::Theme => None,
+            Palette::Protanopia => Some([
+                "#56b4e9", "#0072b2", "#e69f00", "#f0e442", "#009e73", "#cc79a7", "#6e4a00",
+            ]),
+            Palette::Deuteranopia => Some([
+                "#88ccee", "#332288", "#ddaa33", "#ffee99", "#117733", "#aa4499", "#bb5566",
+            ]),
+            Palette::Tritanopia => Some([
+                "#3fd6d6", "#1f4e79", "#ff8c69", "#f2f2f2", "#2e8b57", "#ffb3c6", "#b00020",
+            ]),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    // Indexed by BlockType::index
+    pub pieces: [Color; 7],
+    pub garbage: Color,
+    pub background: Color,
+    pub grid: Color,
+    pub grid_style: GridStyle,
+    pub text: Color,
+    pub muted: Color,
+    pub accent: Color,
+    pub warning: Color,
+    // One square tile per piece in IJLOSTZ order followed by garbage
+    pub skin: Option<Texture2D>,
+    pub backdrop: Option<Texture2D>,
+    pub font: Option<Font>,
+    // Draws a distinct mark on each piece type
+    pub glyphs: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+impl Theme {
+    pub fn classic() -> Self {
+        Theme {
+            name: String::from("Classic"),
+            pieces: [
+                BlockType::I.get_color(),
+                BlockType::J.get_color(),
+                BlockType::L.get_color(),
+                BlockType::O.get_color(),
+                BlockType::S.get_color(),
+                BlockType::T.get_color(),
+                BlockType::Z.get_color(),
+            ],
+            garbage: GREY,
+            background: crate::constants::BLACK,
+            grid: WHITE,
+            grid_style: GridStyle::Lines,
+            text: WHITE,
+            muted: GREY,
+            accent: crate::constants::YELLOW,
+            warning: crate::constants::RED,
+            skin: None,
+            backdrop: None,
+            font: None,
+            glyphs: false,
+        }
+    }
+
+    pub fn built_in() -> Vec<Theme> {
+        let hex = |text| parse_color(text).unwrap_or(WHITE);
+        vec![
+            Theme::classic(),
+            Theme {
+                name: String::from("Guideline"),
+                pieces: [
+                    hex("#00f0f0"),
+                    hex("#0050f0"),
+                    hex("#f0a000"),
+                    hex("#f0f000"),
+                    hex("#00d000"),
+                    hex("#a000f0"),
+                    hex("#f00000"),
+                ],
+                garbage: hex("#6a6a6a"),
+                background: hex("#101018"),
+                grid: hex("#34344a"),
+                ..Theme::classic()
+            },
+            Theme {
+                name: String::from("Pastel"),
+                pieces: [
+                    hex("#9ee6f0"),
+                    hex("#a9b8f5"),
+                    hex("#f7c59f"),
+                    hex("#f6eea0"),
+                    hex("#b5e8a5"),
+                    hex("#d6b0f0"),
+                    hex("#f4a7b0"),
+                ],
+                garbage: hex("#8c8a99"),
+                background: hex("#23212e"),
+                grid: hex("#5a5670"),
+                grid_style: GridStyle::Dots,
+                muted: hex("#a09cb5"),
+                accent: hex("#f6eea0"),
+                warning: hex("#f4a7b0"),
+                ..Theme::classic()
+            },
+            Theme {
+                name: String::from("Paper"),
+                pieces: [
+                    hex("#2a9db5"),
+                    hex("#3355aa"),
+                    hex("#d9822b"),
+                    hex("#d8b400"),
+                    hex("#4c9a2a"),
+                    hex("#8a3fa0"),
+                    hex("#c8323c"),
+                ],
+                garbage: hex("#9a968c"),
+                background: hex("#f3efe4"),
+                grid: hex("#d6d0c0"),
+                text: hex("#1e1e1e"),
+                muted: hex("#77726a"),
+                accent: hex("#c8323c"),
+                warning: hex("#c8323c"),
+                ..Theme::classic()
+            },
+            Theme {
+                name: String::from("Mono"),
+                pieces: [
+                    hex("#f0f0f0"),
+                    hex("#9a9a9a"),
+                    hex("#c4c4c4"),
+                    hex("#e0e0e0"),
+                    hex("#b0b0b0"),
+                    hex("#d2d2d2"),
+                    hex("#888888"),
+                ],
+                garbage: hex("#505050"),
+                grid: hex("#303030"),
+                grid_style: GridStyle::Hidden,
+                muted: hex("#707070"),
+                accent: WHITE,
+                warning: hex("#ff5050"),
+                ..Theme::classic()
+            },
+        ]
+    }
+
+    // Built-in themes followed by the packs listed in `themes/index.txt`.
+    pub async fn load_all() -> Vec<Theme> {
+        let mut themes = Theme::built_in();
+        let index = match load_string(&format!("{}/index.txt", THEME_DIR)).await {
+            Ok(index) => index,
+            Err(_) => return themes,
+        };
+        for dir in index.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            match Theme::load(dir).await {
+                Ok(theme) => themes.push(theme),
+                Err(e) => eprintln!("{}: {}", dir, e),
+            }
+        }
+        themes
+    }
+
+    // Reads `theme.txt` from a pack directory. Missing keys keep the classic look and asset paths
+    // are relative to the pack.
+    pub async fn load(dir: &str) -> Result<Theme, String> {
+        let path = format!("{}/{}", THEME_DIR, dir);
+        let text = load_string(&format!("{}/theme.txt", path))
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut theme = Theme {
+            name: String::from(dir),
+            ..Theme::classic()
+        };
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("expected `key: value`, found: {}", line)),
+            };
+            let color = || parse_color(value).ok_or(format!("invalid color: {}", value));
+            match key {
+                "name" => theme.name = String::from(value),
+                "background" => theme.background = color()?,
+                "grid" => theme.grid = color()?,
+                "grid_style" => {
+                    theme.grid_style =
+                        GridStyle::parse(value).ok_or(format!("invalid grid style: {}", value))?
+                }
+                "text" => theme.text = color()?,
+                "muted" => theme.muted = color()?,
+                "accent" => theme.accent = color()?,
+                "warning" => theme.warning = color()?,
+                "garbage" => theme.garbage = color()?,
+                "skin" => theme.skin = Some(load_image(&path, value).await?),
+                "backdrop" => theme.backdrop = Some(load_image(&path, value).await?),
+                "glyphs" => theme.glyphs = value == "on",
+                "font" => {
+                    let font = load_ttf_font(&format!("{}/{}", path, value))
+                        .await
+                        .map_err(|e| format!("{}: {}", value, e.0))?;
+                    theme.font = Some(font);
+                }
+                _ => match key.chars().next().and_then(BlockType::from_char) {
+                    Some(id) if key.len() == 1 => theme.pieces[id.index()] = color()?,
+                    _ => return Err(format!("unknown key: {}", key)),
+                },
+            }
+        }
+        Ok(theme)
+    }
+
+    // Copy of the theme with the accessibility settings applied. A palette replaces the skin since
+    // the skin's tiles carry their own colors.
+    pub fn with_access(&self, palette: Palette, glyphs: bool) -> Theme {
+        let mut theme = self.clone();
+        if let Some(colors) = palette.colors() {
+            for (piece, color) in theme.pieces.iter_mut().zip(colors) {
+                *piece = parse_color(color).unwrap_or(*piece);
+            }
+            theme.skin = None;
+        }
+        theme.glyphs |= glyphs;
+        theme
+    }
+
+    // Maps a block's engine color to this theme. Colors that are not pieces or garbage pass through.
+    pub fn block_color(&self, color: Color) -> Color {
+        match BlockType::from_color(color) {
+            Some(id) => self.pieces[id.index()],
+            None if color == GREY => self.garbage,
+            None => color,
+        }
+    }
+
+    pub fn draw_block(&self, rect: Rect, color: Color, alpha: f32) {
+        let tile = match BlockType::from_color(color) {
+            Some(id) => Some(id.index()),
+            None if color == GREY => Some(self.pieces.len()),
+            None => None,
+        };
+        if let (Some(skin), Some(tile)) = (self.skin, tile) {
+            let size = skin.height();
+            draw_texture_ex(
+                skin,
+                rect.x,
+                rect.y,
+                Color::new(1.0, 1.0, 1.0, alpha),
+                DrawTextureParams {
+                    dest_size: Some(rect.size()),
+                    source: Some(Rect::new(tile as f32 * size, 0.0, size, size)),
+                    ..Default::default()
+                },
+            );
+        } else {
+            draw_rectangle(
+                rect.x,
+                rect.y,
+                rect.w,
+                rect.h,
+                Color {
+                    a: alpha,
+                    ..self.block_color(color)
+                },
+            );
+        }
+        if let (true, Some(tile)) = (self.glyphs, tile) {
+            draw_glyph(rect, tile, self.block_color(color), alpha);
+        }
+    }
+
+    pub fn draw_grid(&self, well: Rect, cell: f32) {
+        let columns = (well.w / cell).round() as usize;
+        let rows = (well.h / cell).round() as usize;
+        match self.grid_style {
+            GridStyle::Lines => {
+                for i in 0..=columns {
+                    let x = well.x + i as f32 * cell;
+                    draw_line(x, well.y, x, well.bottom(), 1.0, self.grid);
+                }
+                for i in 0..=rows {
+                    let y = well.y + i as f32 * cell;
+                    draw_line(well.x, y, well.right(), y, 1.0, self.grid);
+                }
+            }
+            GridStyle::Dots => {
+                let size = (cell / 10.0).max(2.0);
+                for x in 0..=columns {
+                    for y in 0..=rows {
+                        draw_rectangle(
+                            well.x + x as f32 * cell - size / 2.0,
+                            well.y + y as f32 * cell - size / 2.0,
+                            size,
+                            size,
+                            self.grid,
+                        );
+                    }
+                }
+                draw_rectangle_lines(well.x, well.y, well.w, well.h, 2.0, self.grid);
+            }
+            GridStyle::Hidden => {
+                draw_rectangle_lines(well.x, well.y, well.w, well.h, 2.0, self.grid);
+            }
+        }
+    }
+
+    pub fn draw_background(&self) {
+        clear_background(self.background);
+        if let Some(backdrop) = self.backdrop {
+            draw_texture_ex(
+                backdrop,
+                0.0,
+                0.0,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(screen_width(), screen_height())),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+// One mark per tile in IJLOSTZ order followed by garbage, dark on light blocks and light on dark.
+fn draw_glyph(rect: Rect, tile: usize, fill: Color, alpha: f32) {
+    let luma = 0.299 * fill.r + 0.587 * fill.g + 0.114 * fill.b;
+    let shade = if luma > 0.5 { 0.0 } else { 1.0 };
+    let color = Color::new(shade, shade, shade, 0.6 * alpha);
+    let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
+    let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+    let inset = w * 0.25;
+    let thickness = (w / 8.0).max(1.0);
+    match tile {
+        // I: horizontal bar
+        0 => draw_rectangle(
+            x + inset,
+            cy - thickness / 2.0,
+            w - inset * 2.0,
+            thickness,
+            color,
+        ),
+        // J: small square
+        1 => draw_rectangle(cx - w * 0.15, cy - h * 0.15, w * 0.3, h * 0.3, color),
+        // L: ring
+        2 => draw_circle_lines(cx, cy, w * 0.22, thickness, color),
+        // O: dot
+        3 => draw_circle(cx, cy, w * 0.18, color),
+        // S: rising diagonal
+        4 => draw_line(
+            x + inset,
+            y + h - inset,
+            x + w - inset,
+            y + inset,
+            thickness,
+            color,
+        ),
+        // T: triangle
+        5 => draw_triangle(
+            vec2(cx, y + inset),
+            vec2(x + inset, y + h - inset),
+            vec2(x + w - inset, y + h - inset),
+            color,
+        ),
+        // Z: falling diagonal
+        6 => draw_line(
+            x + inset,
+            y + inset,
+            x + w - inset,
+            y + h - inset,
+            thickness,
+            color,
+        ),
+        // Garbage: cross
+        _ => {
+            draw_line(
+                x + inset,
+                y + inset,
+                x + w - inset,
+                y + h - inset,
+                thickness,
+                color,
+            );
+            draw_line(
+                x + inset,
+                y + h - inset,
+                x + w - inset,
+                y + inset,
+                thickness,
+                color,
+            );
+        }
+    }
+}
+
+async fn load_image(dir: &str, file: &str) -> Result<Texture2D, String> {
+    let texture = load_texture(&format!("{}/{}", dir, file))
+        .await
+        .map_err(|e| e.to_string())?;
+    texture.set_filter(FilterMode::Nearest);
+    Ok(texture)
+}
+
+// `#rrggbb` or `#rrggbbaa`
+pub fn parse_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 0xff };
+    Some(Color::from_rgba(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        alpha,
+    ))
+}
@@ -0,0 +1,237 @@
+use crate::ai::*;
+use crate::constants::*;
+use crate::eval::*;
+use crate::rng::*;
+use crate::sim::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fitness {
+    Lines,
+    Score,
+}
+
+#[derive(Clone)]
+pub struct TrainSettings {
+    pub population: usize,
+    pub generations: u32,
+    pub games: u64,
+    pub pieces: u32,
+    pub fitness: Fitness,
+    pub difficulty: Difficulty,
+    pub threads: usize,
+    pub seed: u64,
+    pub checkpoint: String,
+}
+
+impl Default for TrainSettings {
+    fn default() -> Self {
+        TrainSettings {
+            population: TRAIN_POPULATION,
+            generations: TRAIN_GENERATIONS,
+            games: TRAIN_GAMES,
+            pieces: TRAIN_PIECES,
+            fitness: Fitness::Lines,
+            difficulty: Difficulty::Expert,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+            checkpoint: String::from(TRAIN_CHECKPOINT),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Individual {
+    pub weights: [f64; WEIGHT_NAMES.len()],
+    pub fitness: f64,
+}
+
+fn normalize(mut weights: [f64; WEIGHT_NAMES.len()]) -> [f64; WEIGHT_NAMES.len()] {
+    let length = weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+    if length > 0.0 {
+        for weight in &mut weights {
+            *weight /= length;
+        }
+    }
+    weights
+}
+
+pub struct Trainer {
+    settings: TrainSettings,
+    generation: u32,
+    population: Vec<Individual>,
+}
+
+impl Trainer {
+    pub fn new(settings: TrainSettings) -> Self {
+        let mut rng = Rng::new(settings.seed);
+        let mut population = vec![Individual {
+            weights: normalize(Weights::default().to_array()),
+            fitness: 0.0,
+        }];
+        while population.len() < settings.population {
+            let mut weights = [0.0; WEIGHT_NAMES.len()];
+            for weight in &mut weights {
+                *weight = rng.gen_f64() * 2.0 - 1.0;
+            }
+            population.push(Individual {
+                weights: normalize(weights),
+                fitness: 0.0,
+            });
+        }
+        Trainer {
+            settings,
+            generation: 0,
+            population,
+        }
+    }
+
+    pub fn resume(settings: TrainSettings) -> Result<Self, String> {
+        let text = std::fs::read_to_string(&settings.checkpoint)
+            .map_err(|e| format!("{}: {}", settings.checkpoint, e))?;
+        let mut generation = None;
+        let mut population = Vec::new();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("generation") => {
+                    generation = words.next().and_then(|n| n.parse().ok());
+                }
+                Some("individual") => {
+                    let values: Vec<f64> = words.filter_map(|n| n.parse().ok()).collect();
+                    if values.len() != WEIGHT_NAMES.len() + 1 {
+                        return Err(format!("invalid individual: {}", line));
+                    }
+                    let mut weights = [0.0; WEIGHT_NAMES.len()];
+                    weights.copy_from_slice(&values[1..]);
+                    population.push(Individual {
+                        weights,
+                        fitness: values[0],
+                    });
+                }
+                _ => return Err(format!("unknown checkpoint line: {}", line)),
+            }
+        }
+        if population.is_empty() {
+            return Err(String::from("checkpoint has no individuals"));
+        }
+        Ok(Trainer {
+            settings,
+            generation: generation.ok_or("checkpoint has no generation")?,
+            population,
+        })
+    }
+
+    pub fn save(&self) {
+        let mut lines = vec![format!("generation {}", self.generation)];
+        for individual in &self.population {
+            let weights: Vec<String> = individual.weights.iter().map(|w| w.to_string()).collect();
+            lines.push(format!(
+                "individual {} {}",
+                individual.fitness,
+                weights.join(" ")
+            ));
+        }
+        if let Err(e) = std::fs::write(&self.settings.checkpoint, lines.join("\n")) {
+            eprintln!("Failed to save {}: {}", self.settings.checkpoint, e);
+        }
+    }
+
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.generation >= self.settings.generations
+    }
+
+    pub fn get_best(&self) -> &Individual {
+        self.population
+            .iter()
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+            .unwrap_or(&self.population[0])
+    }
+
+    pub fn get_mean(&self) -> f64 {
+        self.population.iter().map(|i| i.fitness).sum::<f64>() / self.population.len() as f64
+    }
+
+    fn evaluate(&self, weights: &[f64; WEIGHT_NAMES.len()]) -> f64 {
+        let settings = &self.settings;
+        let player = Player::Bot(settings.difficulty, Weights::from_array(*weights));
+        let mut total = 0.0;
+        for game in 0..settings.games {
+            let seed = settings.seed ^ (self.generation as u64 * settings.games + game);
+            match simulate(&player, seed, settings.pieces) {
+                Ok(stats) => {
+                    total += match settings.fitness {
+                        Fitness::Lines => stats.lines as f64,
+                        Fitness::Score => stats.score as f64,
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        total / settings.games.max(1) as f64
+    }
+
+    pub fn evaluate_all(&mut self) {
+        let chunk = self.population.len().div_ceil(self.settings.threads.max(1));
+        let trainer = &*self;
+        let fitness: Vec<f64> = std::thread::scope(|scope| {
+            let workers: Vec<_> = trainer
+                .population
+                .chunks(chunk)
+                .map(|individuals| {
+                    scope.spawn(move || {
+                        individuals
+                            .iter()
+                            .map(|individual| trainer.evaluate(&individual.weights))
+                            .collect::<Vec<f64>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_default())
+                .collect()
+        });
+        for (individual, fitness) in self.population.iter_mut().zip(fitness) {
+            individual.fitness = fitness;
+        }
+        self.population
+            .sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+    }
+
+    pub fn breed(&mut self) {
+        let mut rng = Rng::new(self.settings.seed ^ ((self.generation as u64 + 1) << 32));
+        let mut next: Vec<Individual> = self.population.iter().take(TRAIN_ELITE).cloned().collect();
+        while next.len() < self.settings.population {
+            let a = self.select(&mut rng);
+            let b = self.select(&mut rng);
+            let (share_a, share_b) = match a.fitness + b.fitness > 0.0 {
+                true => (a.fitness, b.fitness),
+                false => (1.0, 1.0),
+            };
+            let mut weights = [0.0; WEIGHT_NAMES.len()];
+            for (i, weight) in weights.iter_mut().enumerate() {
+                *weight = (a.weights[i] * share_a + b.weights[i] * share_b) / (share_a + share_b);
+                if rng.gen_f64() < TRAIN_MUTATION_RATE {
+                    *weight += (rng.gen_f64() * 2.0 - 1.0) * TRAIN_MUTATION_SCALE;
+                }
+            }
+            next.push(Individual {
+                weights: normalize(weights),
+                fitness: 0.0,
+            });
+        }
+        self.population = next;
+        self.generation += 1;
+    }
+
+    fn select(&self, rng: &mut Rng) -> &Individual {
+        (0..TRAIN_TOURNAMENT)
+            .map(|_| &self.population[rng.gen_range(0, self.population.len())])
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+            .unwrap_or(&self.population[0])
+    }
+}
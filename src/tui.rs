@@ -0,0 +1,1316 @@
+use crate::ai::*;
+use crate::constants::*;
+use crate::daily::*;
+use crate::editor::*;
+use crate::json::*;
+use crate::opener::*;
+use crate::play::*;
+use crate::puzzle::*;
+use crate::report::*;
+use crate::save::*;
+use crate::settings::*;
+use crate::stats::*;
+use crate::survival::*;
+use crate::tbp::*;
+use crate::tetrus::*;
+use crate::theme::*;
+use macroquad::color::{Color, WHITE};
+use macroquad::rand::rand;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Control(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+}
+
+// Splits raw terminal input into keys. Letters are lowercased and escape sequences other than the
+// arrows are dropped.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        i += 1;
+        let key = match byte {
+            0x1b if matches!(bytes.get(i), Some(b'[') | Some(b'O')) => {
+                // Parameters up to a final byte in `@..~`
+                let end = bytes[i + 1..]
+                    .iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map_or(bytes.len(), |n| i + 1 + n);
+                i = (end + 1).min(bytes.len());
+                match bytes.get(end) {
+                    Some(b'A') => Key::Up,
+                    Some(b'B') => Key::Down,
+                    Some(b'C') => Key::Right,
+                    Some(b'D') => Key::Left,
+                    _ => continue,
+                }
+            }
+            0x1b => Key::Escape,
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x08 | 0x7f => Key::Backspace,
+            0x01..=0x1a => Key::Control((b'a' + byte - 1) as char),
+            0x20..=0x7e => Key::Char((byte as char).to_ascii_lowercase()),
+            _ => continue,
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+// Terminals send a key's presses and autorepeats but never its release, so a key counts as held
+// for a tap after a press and then for as long as repeats keep arriving.
+#[derive(Clone, Copy)]
+struct Hold {
+    last: f64,
+    repeating: bool,
+}
+
+impl Hold {
+    fn new() -> Self {
+        Hold {
+            last: f64::NEG_INFINITY,
+            repeating: false,
+        }
+    }
+
+    // Returns whether this is a new press rather than an autorepeat
+    fn press(&mut self, now: f64) -> bool {
+        self.repeating = now - self.last < TUI_REPEAT_DELAY;
+        self.last = now;
+        !self.repeating
+    }
+
+    fn is_down(&self, now: f64) -> bool {
+        let hold = match self.repeating {
+            true => TUI_REPEAT_GAP,
+            false => TUI_TAP_TIME,
+        };
+        now - self.last < hold
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Cell {
+    text: char,
+    fg: Color,
+    bg: Color,
+}
+
+// One screen of characters, each with its own foreground and background.
+struct Canvas {
+    cells: Vec<Cell>,
+    background: Color,
+}
+
+impl Canvas {
+    fn new(theme: &Theme) -> Self {
+        let cell = Cell {
+            text: ' ',
+            fg: theme.text,
+            bg: theme.background,
+        };
+        Canvas {
+            cells: vec![cell; TUI_WIDTH * TUI_HEIGHT],
+            background: theme.background,
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, text: &str, fg: Color, bg: Option<Color>) {
+        if y >= TUI_HEIGHT {
+            return;
+        }
+        for (i, c) in text.chars().enumerate() {
+            if x + i >= TUI_WIDTH {
+                break;
+            }
+            let cell = &mut self.cells[y * TUI_WIDTH + x + i];
+            cell.text = c;
+            cell.fg = fg;
+            cell.bg = bg.unwrap_or(cell.bg);
+        }
+    }
+
+    fn text(&mut self, x: usize, y: usize, text: &str, fg: Color) {
+        self.fill(x, y, text, fg, None);
+    }
+
+    // Text ending just before column `right`.
+    fn right(&mut self, right: usize, y: usize, text: &str, fg: Color) {
+        let x = right.saturating_sub(text.chars().count());
+        self.fill(x, y, text, fg, None);
+    }
+
+    fn center(&mut self, y: usize, text: &str, fg: Color) {
+        let x = TUI_WIDTH.saturating_sub(text.chars().count()) / 2;
+        self.fill(x, y, text, fg, None);
+    }
+
+    fn block(&mut self, x: usize, y: usize, theme: &Theme, color: Color, alpha: f32) {
+        let fill = blend(theme.block_color(color), theme.background, alpha);
+        let tile = match BlockType::from_color(color) {
+            Some(id) => Some(id.index()),
+            None if color == GREY => Some(TUI_GLYPHS.len() - 1),
+            None => None,
+        };
+        let text = match tile {
+            Some(tile) if theme.glyphs => TUI_GLYPHS[tile],
+            _ => "  ",
+        };
+        let luma = 0.299 * fill.r + 0.587 * fill.g + 0.114 * fill.b;
+        let shade = if luma > 0.5 { BLACK } else { WHITE };
+        self.fill(x, y, text, shade, Some(fill));
+    }
+
+    // Rows as ANSI text, with a color change only where the colors differ from the last cell.
+    fn lines(&self) -> Vec<String> {
+        self.cells
+            .chunks(TUI_WIDTH)
+            .map(|row| {
+                let mut line = String::new();
+                let mut current = None;
+                for cell in row {
+                    if current != Some((cell.fg, cell.bg)) {
+                        line.push_str(&ansi(38, cell.fg));
+                        line.push_str(&ansi(48, cell.bg));
+                        current = Some((cell.fg, cell.bg));
+                    }
+                    line.push(cell.text);
+                }
+                line.push_str(&ansi(48, self.background));
+                line
+            })
+            .collect()
+    }
+}
+
+fn ansi(layer: u8, color: Color) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u8;
+    format!(
+        "\x1b[{};2;{};{};{}m",
+        layer,
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    )
+}
+
+fn blend(color: Color, background: Color, alpha: f32) -> Color {
+    let mix = |a: f32, b: f32| a * alpha + b * (1.0 - alpha);
+    Color::new(
+        mix(color.r, background.r),
+        mix(color.g, background.g),
+        mix(color.b, background.b),
+        1.0,
+    )
+}
+
+// Raw mode and the alternate screen, both undone when dropped or on a panic. Errors reported
+// meanwhile are printed once the terminal is restored.
+struct Terminal {
+    saved: String,
+    input: Receiver<Vec<u8>>,
+    shown: Vec<String>,
+    // Columns and rows, read through `stty` every TUI_SIZE_POLL seconds rather than every frame
+    size: Option<(usize, usize)>,
+    size_checked: Option<Instant>,
+}
+
+impl Terminal {
+    fn open() -> Result<Terminal, String> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        let (sender, input) = channel();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut buffer = [0; 64];
+            while let Ok(n) = stdin.read(&mut buffer) {
+                if n == 0 || sender.send(buffer[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        let restore = saved.clone();
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            leave(&restore);
+            hook(info);
+        }));
+        print!("\x1b[?1049h\x1b[?25l");
+        hold_reports();
+        Ok(Terminal {
+            saved,
+            input,
+            shown: Vec::new(),
+            size: None,
+            size_checked: None,
+        })
+    }
+
+    fn read(&self) -> Vec<Key> {
+        let mut bytes = Vec::new();
+        while let Ok(chunk) = self.input.try_recv() {
+            bytes.extend(chunk);
+        }
+        parse_keys(&bytes)
+    }
+
+    fn fits(&self) -> bool {
+        self.size
+            .is_none_or(|(columns, rows)| columns >= TUI_WIDTH && rows >= TUI_HEIGHT)
+    }
+
+    // Writes only the rows that changed since the last frame, or a notice if the canvas doesn't fit.
+    fn draw(&mut self, canvas: &Canvas) {
+        if self
+            .size_checked
+            .is_none_or(|checked| checked.elapsed().as_secs_f64() >= TUI_SIZE_POLL)
+        {
+            let size = terminal_size();
+            if size != self.size {
+                // Redraw everything after a resize
+                self.size = size;
+                self.shown.clear();
+            }
+            self.size_checked = Some(Instant::now());
+        }
+        let lines = match self.size {
+            Some((columns, rows)) if !self.fits() => vec![format!(
+                "{}{} {}x{}, needs {}x{}",
+                ansi(48, canvas.background),
+                TUI_TOO_SMALL_TEXT,
+                columns,
+                rows,
+                TUI_WIDTH,
+                TUI_HEIGHT
+            )],
+            _ => canvas.lines(),
+        };
+        let mut out = String::new();
+        if self.shown.is_empty() {
+            out.push_str(&ansi(48, canvas.background));
+            out.push_str("\x1b[2J");
+        }
+        for (y, line) in lines.iter().enumerate() {
+            if self.shown.get(y) != Some(line) {
+                out.push_str(&format!("\x1b[{};1H{}\x1b[K", y + 1, line));
+            }
+        }
+        if !out.is_empty() {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(out.as_bytes());
+            let _ = stdout.flush();
+        }
+        self.shown = lines;
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        leave(&self.saved);
+    }
+}
+
+fn leave(saved: &str) {
+    print!("\x1b[0m\x1b[2J\x1b[?25h\x1b[?1049l");
+    let _ = std::io::stdout().flush();
+    let _ = stty(&[saved]);
+    release_reports();
+}
+
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("stty: {}", e))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("stty: {}", error.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Columns and rows, or None if `stty` can't tell.
+fn terminal_size() -> Option<(usize, usize)> {
+    let size = stty(&["size"]).ok()?;
+    let (rows, columns) = size.split_once(' ')?;
+    Some((columns.parse().ok()?, rows.parse().ok()?))
+}
+
+// macroquad only loads files once a window is open, so the lists are read straight from disk.
+fn read_index(dir: &str) -> Vec<(String, String)> {
+    let index = match std::fs::read_to_string(format!("{}/index.txt", dir)) {
+        Ok(index) => index,
+        Err(_) => return Vec::new(),
+    };
+    index
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .filter_map(|file| {
+            let text = std::fs::read_to_string(format!("{}/{}", dir, file)).ok()?;
+            Some((String::from(file), text))
+        })
+        .collect()
+}
+
+enum State {
+    Welcome,
+    LevelSelect,
+    DailyHistory,
+    Stats,
+    Settings,
+    Editor,
+    Running,
+    GameOver,
+}
+
+// Column of the left border of the player's well, and of the rival's in versus, with the hold
+// pieces drawn outside them. The rows below the well hold the mode's status.
+const WELL_X: usize = 14;
+const RIVAL_X: usize = 40;
+const QUEUE_X: usize = 38;
+const HOLD_X: usize = 5;
+const RIVAL_HOLD_X: usize = 64;
+const BORDER_Y: usize = VISIBLE_ROWS + 1;
+const STATUS_Y: usize = VISIBLE_ROWS + 2;
+
+// Terminal front-end playing the same `Play` as the window.
+pub struct Tui {
+    play: Play,
+    bot_command: Option<String>,
+    state: State,
+    survival_settings: SurvivalSettings,
+    difficulty: Difficulty,
+    puzzles: Vec<Puzzle>,
+    selected: usize,
+    solved: Vec<String>,
+    openers: Vec<Opener>,
+    learned: Vec<String>,
+    editor: Editor,
+    cursor: (usize, usize),
+    daily_history: Vec<DailyRecord>,
+    saved: Option<Json>,
+    stats: Vec<DayStats>,
+    hud: bool,
+    settings: Settings,
+    // Theme packs are only loaded in the window, so only the built-in themes can be picked
+    themes: Vec<Theme>,
+    setting: usize,
+    // The selected theme with the palette and glyph settings applied
+    theme: Theme,
+    clock: Instant,
+    last_tick: f64,
+    lag: f64,
+    soft_drop: [Hold; 2],
+    done: bool,
+}
+
+impl Tui {
+    pub fn new(bot_command: Option<String>) -> Self {
+        let settings = Settings::load();
+        let themes = Theme::built_in();
+        let theme = themes
+            .iter()
+            .find(|t| t.name == settings.theme)
+            .cloned()
+            .unwrap_or_default()
+            .with_access(settings.palette, settings.glyphs);
+        let saved = load_lines(GAME_SAVE);
+        let saved = match Json::parse(&saved.join("\n")) {
+            Ok(json) => Some(json),
+            Err(e) if !saved.is_empty() => {
+                report(format!("{}: {}", GAME_SAVE, e));
+                None
+            }
+            Err(_) => None,
+        };
+        let mut puzzles = Vec::new();
+        for (file, text) in read_index(PUZZLE_DIR) {
+            match Puzzle::parse(&file, &text) {
+                Ok(puzzle) => puzzles.push(puzzle),
+                Err(e) => report(format!("{}: {}", file, e)),
+            }
+        }
+        let mut openers = Vec::new();
+        for (file, text) in read_index(OPENER_DIR) {
+            match Opener::parse(&file, &text) {
+                Ok(opener) => openers.push(opener),
+                Err(e) => report(format!("{}: {}", file, e)),
+            }
+        }
+        Tui {
+            play: Play::new(),
+            bot_command,
+            state: State::Welcome,
+            survival_settings: SurvivalSettings::default(),
+            difficulty: Difficulty::Medium,
+            puzzles,
+            selected: 0,
+            solved: load_lines(PUZZLE_SAVE),
+            openers,
+            learned: load_lines(OPENER_SAVE),
+            editor: Editor::new(),
+            cursor: (0, GRID_HEIGHT - 1),
+            daily_history: load_history(),
+            saved,
+            stats: load_stats(),
+            hud: false,
+            settings,
+            themes,
+            setting: 0,
+            theme,
+            clock: Instant::now(),
+            last_tick: 0.0,
+            lag: 0.0,
+            soft_drop: [Hold::new(); 2],
+            done: false,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let mut terminal = Terminal::open()?;
+        while !self.done {
+            let now = self.clock.elapsed().as_secs_f64();
+            let dt = now - self.last_tick;
+            self.last_tick = now;
+            for key in terminal.read() {
+                self.on_key(key, now);
+            }
+            // The game waits while the terminal is too small to show it
+            if matches!(self.state, State::Running) && !self.done && terminal.fits() {
+                self.update(dt, now);
+            }
+            terminal.draw(&self.draw());
+            std::thread::sleep(Duration::from_secs_f64(TUI_POLL));
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.finish_stats();
+        // A new game replaces the one that could be continued
+        self.saved = None;
+        let bot = (self.play.mode == Mode::Cpu).then(|| self.create_bot());
+        self.play.reset(&self.survival_settings, bot);
+        match self.play.mode {
+            Mode::Puzzle => self.play.set_puzzle(&self.puzzles[self.selected]),
+            Mode::Opener => self.play.set_opener(&self.openers[self.selected]),
+            Mode::Editor => self.play.set_position(
+                self.editor.get_board(),
+                self.editor.get_queue(),
+                self.editor.get_hold(),
+            ),
+            Mode::Daily => self.play.daily = start_daily(&mut self.daily_history),
+            _ => (),
+        }
+        self.start();
+    }
+
+    fn start(&mut self) {
+        self.lag = 0.0;
+        self.soft_drop = [Hold::new(); 2];
+        self.state = State::Running;
+    }
+
+    fn create_bot(&self) -> Box<dyn Controller> {
+        let bot = Bot::new(self.difficulty, rand() as u64);
+        if let Some(command) = &self.bot_command {
+            match TbpBot::new(command) {
+                Ok(tbp) => return Box::new(tbp.with_fallback(bot)),
+                Err(e) => report(e),
+            }
+        }
+        Box::new(bot)
+    }
+
+    fn finish_daily(&mut self) {
+        if let Some(day) = self.play.daily.take() {
+            finish_daily(&mut self.daily_history, day, self.play.get_score());
+        }
+    }
+
+    fn finish_stats(&mut self) {
+        if self.play.session.pieces > 0 {
+            let session = std::mem::take(&mut self.play.session);
+            record_game(&mut self.stats, session, self.play.time);
+        }
+    }
+
+    fn quit(&mut self) {
+        if matches!(self.state, State::Running) {
+            if self.play.is_saveable() && !self.play.tetrus.is_game_over() {
+                save_lines(GAME_SAVE, &[self.play.to_json().to_string()]);
+            } else {
+                self.finish_stats();
+            }
+        }
+        self.done = true;
+    }
+
+    fn is_puzzle_over(&mut self) -> bool {
+        let run = match &self.play.puzzle_run {
+            Some(run) => run,
+            None => return false,
+        };
+        if run.is_solved() {
+            let file = &self.puzzles[self.selected].file;
+            if !self.solved.contains(file) {
+                self.solved.push(file.clone());
+                save_lines(PUZZLE_SAVE, &self.solved);
+            }
+        }
+        run.is_solved() || run.is_failed()
+    }
+
+    fn is_opener_mismatch(&mut self) -> bool {
+        let run = match &self.play.opener_run {
+            Some(run) => run,
+            None => return false,
+        };
+        if run.is_complete() {
+            let file = &self.openers[self.selected].file;
+            if !self.learned.contains(file) {
+                self.learned.push(file.clone());
+                save_lines(OPENER_SAVE, &self.learned);
+            }
+        }
+        run.is_mismatch()
+    }
+
+    fn update(&mut self, dt: f64, now: f64) {
+        self.play.time += dt;
+        if self.play.update_garbage(dt) {
+            self.game_over();
+            return;
+        }
+        let soft_drop = self.soft_drop[0].is_down(now);
+        self.play.tetrus.set_soft_drop(soft_drop);
+        if let (Some(rival), None) = (&mut self.play.rival, &self.play.bot) {
+            rival.set_soft_drop(self.soft_drop[1].is_down(now));
+        }
+        self.lag += dt;
+        while self.lag >= 1.0 / FRAME_RATE && !self.play.is_over() {
+            if self.is_opener_mismatch() {
+                self.lag = 0.0;
+                break;
+            }
+            self.lag -= 1.0 / FRAME_RATE;
+            self.play.update_frame(soft_drop);
+        }
+        if self.play.is_lost(0)
+            || self.play.master.is_finished()
+            || self.is_puzzle_over()
+            || self.play.is_versus_over()
+        {
+            self.game_over();
+        }
+    }
+
+    fn game_over(&mut self) {
+        self.finish_daily();
+        self.finish_stats();
+        if self.play.visibility != Visibility::Normal {
+            self.play.reveal = u64::MAX;
+        }
+        self.state = State::GameOver;
+    }
+
+    fn on_key(&mut self, key: Key, now: f64) {
+        if key == Key::Control('c') {
+            self.quit();
+            return;
+        }
+        match self.state {
+            State::Welcome => self.welcome_key(key),
+            State::LevelSelect => self.level_select_key(key),
+            State::DailyHistory | State::Stats => match key {
+                Key::Enter => self.state = State::Welcome,
+                Key::Escape => self.quit(),
+                _ => (),
+            },
+            State::Settings => self.settings_key(key),
+            State::Editor => self.editor_key(key),
+            State::Running => self.running_key(key, now),
+            State::GameOver => self.game_over_key(key),
+        }
+    }
+
+    fn welcome_key(&mut self, key: Key) {
+        let mode = self.play.mode;
+        match key {
+            Key::Char('c') if self.saved.is_some() => {
+                if let Some(saved) = self.saved.take() {
+                    match self.play.resume(&saved) {
+                        Ok(()) => {
+                            remove_save(GAME_SAVE);
+                            self.start();
+                        }
+                        Err(e) => report(format!("{}: {}", GAME_SAVE, e)),
+                    }
+                }
+            }
+            Key::Char('a') => self.play.mode = mode.prev(),
+            Key::Char('d') => self.play.mode = mode.next(),
+            Key::Char('h') if mode == Mode::Survival => {
+                self.survival_settings.holes = self.survival_settings.holes.next()
+            }
+            Key::Char('h') if mode == Mode::Daily => self.state = State::DailyHistory,
+            Key::Char('h') if mode == Mode::Cpu => self.difficulty = self.difficulty.next(),
+            Key::Char('v') => self.play.visibility = self.play.visibility.next(),
+            Key::Char('s') => self.state = State::Stats,
+            Key::Char('o') => self.state = State::Settings,
+            Key::Char(' ') if mode == Mode::Puzzle => {
+                self.selected = self.selected.min(self.puzzles.len().saturating_sub(1));
+                self.state = State::LevelSelect;
+            }
+            Key::Char(' ') if mode == Mode::Opener => {
+                self.selected = self.selected.min(self.openers.len().saturating_sub(1));
+                self.state = State::LevelSelect;
+            }
+            Key::Char(' ') if mode == Mode::Editor => self.state = State::Editor,
+            Key::Char(' ') => self.reset(),
+            Key::Escape => self.quit(),
+            _ => (),
+        }
+    }
+
+    fn settings_key(&mut self, key: Key) {
+        match key {
+            Key::Char('w') | Key::Up => self.setting = self.setting.saturating_sub(1),
+            Key::Char('s') | Key::Down => self.setting = (self.setting + 1).min(2),
+            Key::Char('a') | Key::Left => self.change_setting(false),
+            Key::Char('d') | Key::Right => self.change_setting(true),
+            Key::Enter => self.state = State::Welcome,
+            Key::Escape => self.quit(),
+            _ => (),
+        }
+    }
+
+    // Effects only play in the window, so only the theme, palette and glyphs are listed.
+    fn get_settings(&self) -> Vec<(&str, String)> {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        vec![
+            ("Theme", self.theme.name.clone()),
+            ("Palette", String::from(self.settings.palette.name())),
+            ("Glyphs", String::from(on_off(self.settings.glyphs))),
+        ]
+    }
+
+    fn change_setting(&mut self, forward: bool) {
+        let step = |value: usize, count: usize| match forward {
+            true => (value + 1) % count,
+            false => (value + count - 1) % count,
+        };
+        match self.setting {
+            0 => {
+                let current = self.themes.iter().position(|t| t.name == self.theme.name);
+                let theme = step(current.unwrap_or(0), self.themes.len());
+                self.settings.theme = self.themes[theme].name.clone();
+            }
+            1 => {
+                let palettes = Palette::ALL;
+                let current = palettes.iter().position(|&p| p == self.settings.palette);
+                self.settings.palette = palettes[step(current.unwrap_or(0), palettes.len())];
+            }
+            _ => self.settings.glyphs = !self.settings.glyphs,
+        }
+        self.theme = self
+            .themes
+            .iter()
+            .find(|t| t.name == self.settings.theme)
+            .cloned()
+            .unwrap_or_default()
+            .with_access(self.settings.palette, self.settings.glyphs);
+        self.settings.save();
+    }
+
+    fn level_select_key(&mut self, key: Key) {
+        let count = match self.play.mode {
+            Mode::Opener => self.openers.len(),
+            _ => self.puzzles.len(),
+        };
+        match key {
+            Key::Char('w') | Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Char('s') | Key::Down => {
+                self.selected = (self.selected + 1).min(count.saturating_sub(1))
+            }
+            Key::Char(' ') if count > 0 => {
+                self.play.retries = 0;
+                self.reset();
+            }
+            Key::Enter => self.state = State::Welcome,
+            Key::Escape => self.quit(),
+            _ => (),
+        }
+    }
+
+    fn editor_key(&mut self, key: Key) {
+        let (x, y) = self.cursor;
+        match key {
+            Key::Left => self.cursor.0 = x.saturating_sub(1),
+            Key::Right => self.cursor.0 = (x + 1).min(GRID_WIDTH - 1),
+            Key::Up => self.cursor.1 = (y - 1).max(HIDDEN_ROWS),
+            Key::Down => self.cursor.1 = (y + 1).min(GRID_HEIGHT - 1),
+            Key::Char('p') => self.editor.paint(x, y),
+            Key::Char('x') => self.editor.erase(x, y),
+            Key::Char(c @ '1'..='8') => self.editor.set_brush(c as usize - '1' as usize),
+            Key::Char(c) if "ijlostz".contains(c) => {
+                if let Some(id) = BlockType::from_char(c.to_ascii_uppercase()) {
+                    self.editor.push_queue(id);
+                }
+            }
+            Key::Control('s') => save_lines(EDITOR_SAVE, &[self.editor.to_text()]),
+            Key::Control('l') => match Editor::parse(&load_lines(EDITOR_SAVE).join("\n")) {
+                Ok(editor) => self.editor = editor,
+                Err(e) => report(format!("{}: {}", EDITOR_SAVE, e)),
+            },
+            Key::Backspace => self.editor.pop_queue(),
+            Key::Char('h') => self.editor.cycle_hold(),
+            Key::Char('c') => self.editor.clear(),
+            Key::Char(' ') => self.reset(),
+            Key::Enter => self.state = State::Welcome,
+            Key::Escape => self.quit(),
+            _ => (),
+        }
+    }
+
+    fn running_key(&mut self, key: Key, now: f64) {
+        match key {
+            Key::Escape => return self.quit(),
+            Key::Tab => self.hud = !self.hud,
+            Key::Char('u') if self.play.is_practice() => {
+                self.play.undo();
+            }
+            Key::Char('r') if self.play.is_practice() => {
+                self.play.redo();
+            }
+            _ => (),
+        }
+        if self.is_opener_mismatch() {
+            if key == Key::Enter {
+                self.play.retries += 1;
+                self.reset();
+            }
+            return;
+        }
+        let soft_drop = key == Key::Char('s') && self.soft_drop[0].press(now);
+        let tetrus = &mut self.play.tetrus;
+        if tetrus.is_active() {
+            let movement = match key {
+                Key::Char('a') => Some(Movement::Left),
+                Key::Char('d') => Some(Movement::Right),
+                Key::Char('w') => Some(Movement::Rotate),
+                Key::Char(' ') => Some(Movement::Drop),
+                _ => None,
+            };
+            if let Some(movement) = movement {
+                tetrus.player_move(movement);
+            }
+            if movement.is_some() || soft_drop {
+                self.play.on_key();
+            }
+            if movement == Some(Movement::Drop) || soft_drop {
+                self.play.finesse.on_drop();
+            }
+            if key == Key::Char('q') {
+                self.play.tetrus.hold_piece();
+                self.play.on_key();
+            }
+        }
+        if let (Some(rival), None) = (&mut self.play.rival, &self.play.bot) {
+            if key == Key::Down {
+                self.soft_drop[1].press(now);
+            }
+            if rival.is_active() {
+                match key {
+                    Key::Left => rival.player_move(Movement::Left),
+                    Key::Right => rival.player_move(Movement::Right),
+                    Key::Up => rival.player_move(Movement::Rotate),
+                    Key::Enter => rival.player_move(Movement::Drop),
+                    Key::Backspace => rival.hold_piece(),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn game_over_key(&mut self, key: Key) {
+        let mode = self.play.mode;
+        match key {
+            Key::Char(' ') => self.reset(),
+            Key::Char('u') if self.play.is_practice() && self.play.undo() => self.start(),
+            Key::Enter if mode == Mode::Puzzle || mode == Mode::Opener => {
+                self.state = State::LevelSelect
+            }
+            Key::Enter if mode == Mode::Editor => self.state = State::Editor,
+            Key::Enter => self.state = State::Welcome,
+            Key::Escape => self.quit(),
+            _ => (),
+        }
+    }
+
+    fn draw(&self) -> Canvas {
+        let mut canvas = Canvas::new(&self.theme);
+        match self.state {
+            State::Welcome => self.draw_welcome(&mut canvas),
+            State::LevelSelect => self.draw_level_select(&mut canvas),
+            State::DailyHistory => self.draw_daily_history(&mut canvas),
+            State::Stats => self.draw_stats(&mut canvas),
+            State::Settings => self.draw_settings(&mut canvas),
+            State::Editor => self.draw_editor(&mut canvas),
+            State::Running => self.draw_running(&mut canvas),
+            State::GameOver => self.draw_game_over(&mut canvas),
+        }
+        canvas
+    }
+
+    fn draw_welcome(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        canvas.center(6, TETRUS_TEXT, theme.text);
+        canvas.center(8, SPACE_TEXT, theme.text);
+        canvas.center(10, &format!("< {} >", self.play.mode.name()), theme.text);
+        let option = match self.play.mode {
+            Mode::Survival => Some(format!("Holes: {}", self.survival_settings.holes.name())),
+            Mode::Cpu => Some(match &self.bot_command {
+                Some(command) => format!("Bot: {}", command),
+                None => format!("Difficulty: {}", self.difficulty.name()),
+            }),
+            Mode::Daily => {
+                let day = today();
+                Some(match self.daily_history.iter().find(|r| r.day == day) {
+                    Some(record) => format!("{}  played: {:05}", date_string(day), record.score),
+                    None => format!("{}  not played yet", date_string(day)),
+                })
+            }
+            _ => None,
+        };
+        if let Some(option) = option {
+            canvas.center(12, &option, theme.muted);
+        }
+        let stack_text = format!("Stack: {}", self.play.visibility.name());
+        canvas.center(13, &stack_text, theme.muted);
+        if let Some(saved) = &self.saved {
+            let continue_text =
+                format!("C: Continue {}", saved.get_str("mode").unwrap_or_default());
+            canvas.center(15, &continue_text, theme.text);
+        }
+        canvas.center(TUI_HEIGHT - 1, TUI_WELCOME_HELP_TEXT, theme.muted);
+    }
+
+    fn draw_level_select(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        let (title, empty, levels): (&str, &str, Vec<(bool, &str)>) = match self.play.mode {
+            Mode::Opener => (
+                OPENERS_TEXT,
+                NO_OPENERS_TEXT,
+                self.openers
+                    .iter()
+                    .map(|o| (self.learned.contains(&o.file), o.name.as_str()))
+                    .collect(),
+            ),
+            _ => (
+                PUZZLES_TEXT,
+                NO_PUZZLES_TEXT,
+                self.puzzles
+                    .iter()
+                    .map(|p| (self.solved.contains(&p.file), p.name.as_str()))
+                    .collect(),
+            ),
+        };
+        canvas.center(1, title, theme.text);
+        if levels.is_empty() {
+            canvas.text(2, 3, empty, theme.muted);
+        }
+        let rows = TUI_HEIGHT - 4;
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (i, (done, name)) in levels.into_iter().enumerate().skip(first).take(rows) {
+            let mark = if done { "[x]" } else { "[ ]" };
+            let color = if i == self.selected {
+                theme.accent
+            } else {
+                theme.text
+            };
+            canvas.text(2, 3 + i - first, &format!("{} {}", mark, name), color);
+        }
+    }
+
+    fn draw_daily_history(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        canvas.center(1, DAILY_TEXT, theme.text);
+        let best = self
+            .daily_history
+            .iter()
+            .map(|r| r.score)
+            .max()
+            .unwrap_or(0);
+        for (i, record) in self.daily_history.iter().rev().take(20).enumerate() {
+            let color = if record.score == best {
+                theme.accent
+            } else {
+                theme.text
+            };
+            let line = format!("{}  {:05}", date_string(record.day), record.score);
+            canvas.text(2, 3 + i, &line, color);
+        }
+    }
+
+    fn draw_stats(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        canvas.center(0, STATS_TEXT, theme.text);
+        let total = lifetime(&self.stats);
+        let time = total.time as u64;
+        let lines = [
+            format!("Games: {}", total.games),
+            format!(
+                "Time played: {}:{:02}:{:02}",
+                time / 3600,
+                time / 60 % 60,
+                time % 60
+            ),
+            format!("Pieces: {}", total.pieces),
+            format!("Lines: {}", total.lines),
+            format!(
+                "Clears: {} / {} / {} / {}",
+                total.clears[0], total.clears[1], total.clears[2], total.clears[3]
+            ),
+            format!("T-spins: {}", total.tspins),
+            format!("Perfect clears: {}", total.perfects),
+            format!("Best combo: {}", total.best_combo),
+            format!("Pieces per second: {:.2}", total.pps()),
+            format!("Attack per minute: {:.2}", total.apm()),
+            format!("Keys per piece: {:.2}", total.kpp()),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            canvas.text(2, 2 + i, line, theme.text);
+        }
+        let last = today();
+        let first = last + 1 - STATS_DAYS;
+        let days: Vec<Stats> = (first..=last)
+            .map(|day| match self.stats.iter().find(|r| r.day == day) {
+                Some(record) => record.stats.clone(),
+                None => Stats::new(),
+            })
+            .collect();
+        let charts: [(&str, Vec<f64>); 2] = [
+            (
+                "Lines per day",
+                days.iter().map(|n| n.lines as f64).collect(),
+            ),
+            ("Pieces per second", days.iter().map(|n| n.pps()).collect()),
+        ];
+        let levels: Vec<char> = TUI_SPARK.chars().collect();
+        for (i, (title, values)) in charts.iter().enumerate() {
+            let y = 14 + i * 3;
+            let max = values.iter().cloned().fold(0.0, f64::max);
+            canvas.text(2, y, &format!("{} (max {:.2})", title, max), theme.muted);
+            let bars: String = values
+                .iter()
+                .map(|value| {
+                    let level = match max > 0.0 {
+                        true => (value / max * (levels.len() - 1) as f64).round() as usize,
+                        false => 0,
+                    };
+                    levels[level].to_string().repeat(3)
+                })
+                .collect();
+            canvas.text(2, y + 1, &bars, theme.accent);
+        }
+        let y = 14 + charts.len() * 3 - 1;
+        let width = days.len() * 3;
+        canvas.text(2, y, &date_string(first)[5..], theme.muted);
+        canvas.right(2 + width, y, &date_string(last)[5..], theme.muted);
+    }
+
+    // Border and empty cells of a well whose left border is at column `x`.
+    fn draw_settings(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        canvas.center(2, SETTINGS_TEXT, theme.text);
+        let settings = self.get_settings();
+        for (i, (label, value)) in settings.iter().enumerate() {
+            let color = if i == self.setting {
+                theme.accent
+            } else {
+                theme.text
+            };
+            canvas.text(4, 5 + i * 2, &format!("{}: < {} >", label, value), color);
+        }
+
+        // Preview of the pieces and grid in the selected theme
+        let y = 6 + settings.len() * 2;
+        for (i, c) in "IJLOSTZ".chars().enumerate() {
+            if let Some(id) = BlockType::from_char(c) {
+                canvas.block(4 + i * 2, y, theme, id.get_color(), 1.0);
+            }
+        }
+        for x in 0..GRID_WIDTH - 1 {
+            canvas.block(4 + x * 2, y + 2, theme, GREY, 1.0);
+        }
+        canvas.center(TUI_HEIGHT - 1, TUI_SETTINGS_HELP_TEXT, theme.muted);
+    }
+
+    fn draw_frame(&self, canvas: &mut Canvas, x: usize) {
+        let theme = &self.theme;
+        let empty = match theme.grid_style {
+            GridStyle::Hidden => "  ",
+            _ => " .",
+        };
+        for y in 1..=VISIBLE_ROWS {
+            canvas.text(x, y, "|", theme.grid);
+            for column in 0..GRID_WIDTH {
+                canvas.text(x + 1 + column * 2, y, empty, theme.grid);
+            }
+            canvas.text(x + 1 + GRID_WIDTH * 2, y, "|", theme.grid);
+        }
+        let bottom = format!("+{}+", "-".repeat(GRID_WIDTH * 2));
+        canvas.text(x, BORDER_Y, &bottom, theme.grid);
+    }
+
+    fn draw_cell(&self, canvas: &mut Canvas, x: usize, block: &Block, alpha: f32) {
+        if block.position.y >= HIDDEN_ROWS && alpha > 0.0 {
+            let column = x + 1 + block.position.x * 2;
+            let row = 1 + block.position.y - HIDDEN_ROWS;
+            canvas.block(column, row, &self.theme, block.color, alpha);
+        }
+    }
+
+    fn draw_well(&self, canvas: &mut Canvas, tetrus: &Tetrus, x: usize) {
+        self.draw_frame(canvas, x);
+        for block in &tetrus.inactive {
+            self.draw_cell(canvas, x, block, self.play.get_alpha(tetrus, block));
+        }
+        for block in &tetrus.active {
+            self.draw_cell(canvas, x, block, 1.0);
+        }
+    }
+
+    fn draw_editor(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        canvas.text(0, 0, TUI_EDITOR_HELP_TEXT, theme.text);
+        self.draw_frame(canvas, WELL_X);
+        for block in self.editor.get_board() {
+            self.draw_cell(canvas, WELL_X, block, 1.0);
+        }
+        let (x, y) = self.cursor;
+        canvas.text(WELL_X + 1 + x * 2, 1 + y - HIDDEN_ROWS, "<>", theme.accent);
+        canvas.text(0, 1, self.editor.get_comment(), theme.muted);
+        for (i, c) in EDITOR_BRUSHES.chars().enumerate() {
+            let color = BlockType::from_char(c).map_or(GREY, |id| id.get_color());
+            canvas.block(WELL_X + 1 + i * 3, STATUS_Y, theme, color, 1.0);
+            if i == self.editor.get_brush() {
+                canvas.text(WELL_X + i * 3, STATUS_Y, ">", theme.text);
+            }
+        }
+        let queue: String = self
+            .editor
+            .get_queue()
+            .iter()
+            .map(|id| id.to_char())
+            .collect();
+        let queue_x = WELL_X + 2 + EDITOR_BRUSHES.len() * 3;
+        let hold: String = self
+            .editor
+            .get_hold()
+            .map(|id| id.to_char())
+            .into_iter()
+            .collect();
+        let queue_text = format!("Queue: {}  Hold: {}", queue, hold);
+        canvas.text(queue_x, STATUS_Y, &queue_text, theme.text);
+        canvas.text(0, STATUS_Y + 1, TUI_QUEUE_HELP_TEXT, theme.muted);
+    }
+
+    fn draw_running(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        let right = WELL_X + 2 + GRID_WIDTH * 2;
+        match &self.play.rival {
+            Some(rival) => {
+                for (i, tetrus) in [&self.play.tetrus, rival].into_iter().enumerate() {
+                    let x = [WELL_X, RIVAL_X][i];
+                    self.draw_well(canvas, tetrus, x);
+                    self.draw_hold(canvas, tetrus, [HOLD_X, RIVAL_HOLD_X][i]);
+                    let name = match (i, &self.play.bot) {
+                        (1, Some(_)) => String::from("CPU"),
+                        _ => format!("P{}", i + 1),
+                    };
+                    let sent = format!("{}  sent {}", name, self.play.versus.get_sent(i));
+                    canvas.text(x + 1, 0, &sent, theme.text);
+                    let meter = [right, RIVAL_X - 1][i];
+                    let pending = self.play.versus.get_pending(i).min(VISIBLE_ROWS);
+                    for y in BORDER_Y - pending..BORDER_Y {
+                        canvas.fill(meter, y, " ", theme.warning, Some(theme.warning));
+                    }
+                }
+                let time = format!("{:04}", self.play.time as u64);
+                canvas.right(RIVAL_X + 1 + GRID_WIDTH * 2, 0, &time, theme.text);
+            }
+            None => {
+                self.draw_well(canvas, &self.play.tetrus, WELL_X);
+                self.draw_hold(canvas, &self.play.tetrus, HOLD_X);
+                let score = format!("{:05}", self.play.get_score());
+                canvas.text(WELL_X + 1, 0, &score, theme.text);
+                let time = format!("{:04}", self.play.time as u64);
+                canvas.right(right - 1, 0, &time, theme.text);
+            }
+        }
+        if self.hud {
+            let items = self.play.get_hud();
+            let top = BORDER_Y - items.len();
+            for (i, (label, value)) in items.iter().enumerate() {
+                canvas.text(1, top + i, label, theme.muted);
+                canvas.right(WELL_X - 1, top + i, value, theme.text);
+            }
+        }
+        match self.play.mode {
+            Mode::Survival | Mode::Daily => {
+                let width = (GRID_WIDTH as f64 * 2.0 * self.play.survival.get_progress()) as usize;
+                let bar = " ".repeat(width);
+                canvas.fill(WELL_X + 1, STATUS_Y, &bar, theme.muted, Some(theme.muted));
+            }
+            Mode::Master => {
+                let master = &self.play.master;
+                let level_text = format!("LV {:03}/{}", master.get_level(), master.get_section());
+                canvas.text(WELL_X + 1, STATUS_Y, &level_text, theme.text);
+                let grade_text = format!("Grade {}", master.get_grade());
+                canvas.right(right - 1, STATUS_Y, &grade_text, theme.text);
+            }
+            Mode::Puzzle => self.draw_puzzle(canvas),
+            Mode::Finesse => self.draw_finesse(canvas),
+            Mode::Opener => self.draw_opener(canvas),
+            Mode::Endless | Mode::Versus | Mode::Cpu | Mode::Editor => (),
+        }
+    }
+
+    // A piece at half height, two cells to a character row, with its top left at `x`, `y`.
+    fn draw_piece(&self, canvas: &mut Canvas, id: &BlockType, x: usize, y: usize, alpha: f32) {
+        let theme = &self.theme;
+        let blocks = id.get_blocks();
+        let color = blend(theme.block_color(id.get_color()), theme.background, alpha);
+        let filled = |x: usize, y: usize| blocks.contains(&(x + 4, y));
+        for row in 0..2 {
+            for column in 0..2 {
+                let (top, bottom) = (filled(column, row * 2), filled(column, row * 2 + 1));
+                if !top && !bottom {
+                    continue;
+                }
+                let fg = if top { color } else { theme.background };
+                let bg = if bottom { color } else { theme.background };
+                canvas.fill(x + column * 2, y + row, "▀▀", fg, Some(bg));
+            }
+        }
+    }
+
+    fn draw_queue(&self, canvas: &mut Canvas) {
+        let queue = self.play.tetrus.get_queue();
+        for (i, id) in queue.iter().take(PREVIEW_COUNT).enumerate() {
+            self.draw_piece(canvas, id, QUEUE_X, 1 + i * 3, 1.0);
+        }
+    }
+
+    // The held piece beside a well, dimmed once it has been used for this piece.
+    fn draw_hold(&self, canvas: &mut Canvas, tetrus: &Tetrus, x: usize) {
+        if let Some(id) = tetrus.get_hold() {
+            let alpha = if tetrus.is_hold_used() { 0.5 } else { 1.0 };
+            self.draw_piece(canvas, id, x, 1, alpha);
+        }
+    }
+
+    fn draw_puzzle(&self, canvas: &mut Canvas) {
+        if let Some(run) = &self.play.puzzle_run {
+            let theme = &self.theme;
+            let goal_text = self.puzzles[self.selected].goal.describe();
+            canvas.text(WELL_X + 1, STATUS_Y, &goal_text, theme.text);
+            let pieces_text = format!("Pieces {}", run.get_remaining());
+            canvas.text(WELL_X + 1, STATUS_Y + 1, &pieces_text, theme.text);
+        }
+        self.draw_queue(canvas);
+    }
+
+    fn draw_finesse(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        let finesse = &self.play.finesse;
+        let faults_text = format!("Faults {}/{}", finesse.get_faults(), finesse.get_pieces());
+        canvas.text(WELL_X + 1, STATUS_Y + 1, &faults_text, theme.text);
+        if let Some(last) = finesse.get_last() {
+            let last_text = format!("{} {}/{}", last.id.to_char(), last.presses, last.minimum);
+            let color = if last.is_fault() {
+                theme.warning
+            } else {
+                theme.text
+            };
+            canvas.right(WELL_X + 1 + GRID_WIDTH * 2, STATUS_Y + 1, &last_text, color);
+        }
+        for (i, id) in "IJLOSTZ".chars().enumerate() {
+            let line = format!("{} {}", id, finesse.get_by_piece()[i]);
+            canvas.text(1, 1 + i, &line, theme.muted);
+        }
+        for (x, faults) in finesse.get_by_column().iter().enumerate() {
+            let column = WELL_X + 1 + x * 2;
+            canvas.text(column, STATUS_Y, &faults.to_string(), theme.muted);
+        }
+    }
+
+    fn draw_opener(&self, canvas: &mut Canvas) {
+        let run = match &self.play.opener_run {
+            Some(run) => run,
+            None => return,
+        };
+        let theme = &self.theme;
+        for block in run.get_remaining(&self.play.tetrus.inactive) {
+            self.draw_cell(canvas, WELL_X, block, TARGET_ALPHA);
+        }
+        // Keep the falling piece on top of the targets
+        for block in &self.play.tetrus.active {
+            self.draw_cell(canvas, WELL_X, block, 1.0);
+        }
+        let name = &self.openers[self.selected].name;
+        canvas.text(WELL_X + 1, STATUS_Y, name, theme.text);
+        let (stage, stages) = run.get_stage();
+        let status_text = match (run.is_complete(), stages) {
+            (true, _) => String::from("Complete"),
+            (false, 1) => format!("Retries {}", self.play.retries),
+            (false, _) => format!("Stage {}/{}  Retries {}", stage, stages, self.play.retries),
+        };
+        canvas.right(
+            WELL_X + 1 + GRID_WIDTH * 2,
+            STATUS_Y,
+            &status_text,
+            theme.text,
+        );
+        if run.is_mismatch() {
+            canvas.text(WELL_X + 1, STATUS_Y + 1, MISMATCH_TEXT, theme.warning);
+        }
+        self.draw_queue(canvas);
+    }
+
+    fn draw_game_over(&self, canvas: &mut Canvas) {
+        let theme = &self.theme;
+        if self.play.visibility != Visibility::Normal {
+            self.draw_well(canvas, &self.play.tetrus, WELL_X);
+        }
+        if self.play.mode == Mode::Master {
+            let grade_text = format!("Grade {}", self.play.master.get_grade());
+            canvas.center(8, &grade_text, theme.text);
+        }
+        canvas.center(10, self.play.get_title(), theme.text);
+        canvas.center(12, &self.play.get_result(), theme.text);
+        canvas.center(14, SPACE_TEXT, theme.text);
+        if self.play.is_practice() && self.play.can_undo() {
+            canvas.center(15, UNDO_TEXT, theme.text);
+        }
+    }
+}
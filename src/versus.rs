@@ -0,0 +1,139 @@
+use crate::constants::*;
+use crate::rng::*;
+use crate::tetrus::*;
+use std::collections::VecDeque;
+
+pub fn get_attack(clear: &LineClear) -> usize {
+    let mut attack = if clear.tspin {
+        ATTACK_TSPIN[clear.lines.min(3)]
+    } else {
+        ATTACK_LINES[clear.lines.min(4)]
+    };
+    attack += ATTACK_COMBO[(clear.combo as usize).min(ATTACK_COMBO.len() - 1)];
+    if clear.b2b {
+        attack += ATTACK_B2B;
+    }
+    if clear.perfect {
+        attack += ATTACK_PERFECT;
+    }
+    attack
+}
+
+pub struct Versus {
+    incoming: [VecDeque<usize>; 2],
+    sent: [usize; 2],
+    topped_out: [bool; 2],
+    rng: Rng,
+}
+
+impl Versus {
+    pub fn new(seed: u64) -> Self {
+        Versus {
+            incoming: [VecDeque::new(), VecDeque::new()],
+            sent: [0, 0],
+            topped_out: [false, false],
+            rng: Rng::new(seed),
+        }
+    }
+
+    // Garbage that tops the player out is the last raised; the rest is dropped.
+    pub fn on_events(&mut self, player: usize, events: &[Event], tetrus: &mut Tetrus) {
+        let mut locked = false;
+        let mut cleared = false;
+        for event in events {
+            match event {
+                Event::Lock => locked = true,
+                Event::Clear(clear) => {
+                    cleared = true;
+                    let attack = self.cancel(player, get_attack(clear));
+                    if attack > 0 {
+                        self.sent[player] += attack;
+                        self.incoming[1 - player].push_back(attack);
+                    }
+                }
+                Event::Spawn | Event::Hold => (),
+            }
+        }
+        if locked && !cleared {
+            while let Some(rows) = self.incoming[player].pop_front() {
+                let hole = self.rng.gen_range(0, GRID_WIDTH);
+                for _ in 0..rows {
+                    if tetrus.raise_garbage(hole) || tetrus.is_game_over() {
+                        self.topped_out[player] = true;
+                        self.incoming[player].clear();
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn cancel(&mut self, player: usize, mut attack: usize) -> usize {
+        while attack > 0 {
+            match self.incoming[player].front_mut() {
+                Some(rows) if *rows > attack => {
+                    *rows -= attack;
+                    attack = 0;
+                }
+                Some(rows) => {
+                    attack -= *rows;
+                    self.incoming[player].pop_front();
+                }
+                None => break,
+            }
+        }
+        attack
+    }
+
+    pub fn get_pending(&self, player: usize) -> usize {
+        self.incoming[player].iter().sum()
+    }
+
+    pub fn is_topped_out(&self, player: usize) -> bool {
+        self.topped_out[player]
+    }
+
+    pub fn get_sent(&self, player: usize) -> usize {
+        self.sent[player]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn garbage_stops_at_top_out() {
+        let mut versus = Versus::new(0);
+        let attack = Event::Clear(LineClear {
+            lines: 4,
+            perfect: true,
+            tspin: false,
+            combo: 10,
+            b2b: true,
+        });
+        versus.on_events(1, &[attack], &mut Tetrus::new());
+        // Sixteen rows with a hole on the right
+        let mut tetrus = Tetrus::new();
+        tetrus.set_board(
+            (GRID_HEIGHT - 16..GRID_HEIGHT)
+                .flat_map(|y| {
+                    (0..GRID_WIDTH - 1).map(move |x| Block {
+                        position: Position::new((x, y)),
+                        color: GREY,
+                        locked_at: 0,
+                    })
+                })
+                .collect(),
+        );
+        versus.on_events(0, &[Event::Lock], &mut tetrus);
+        assert!(versus.is_topped_out(0));
+        assert_eq!(versus.get_pending(0), 0);
+        // Raising stops once the stack reaches the hidden rows
+        assert!(tetrus.inactive.iter().all(|b| b.position.y >= 3));
+        // Spawning and falling onto the raised stack must not underflow
+        for _ in 0..600 {
+            tetrus.update();
+        }
+    }
+}